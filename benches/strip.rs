@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+use ansi_strip::{strip_ansi, NonEscBytes};
+
+/// About a megabyte of log lines, one in `every` of them coloured.
+fn log(every: usize) -> String {
+    let mut log = String::new();
+    for i in 0..16_000 {
+        if every > 0 && i % every == 0 {
+            log.push_str("\x1b[1;31merror\x1b[0m: ");
+        } else {
+            log.push_str("info: ");
+        }
+        log.push_str("request handled in 12ms, status 200, path /api/v1/items\n");
+    }
+    log
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strip");
+    for (name, every) in [("plain", 0), ("sparse", 100), ("dense", 1)] {
+        let input = log(every);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(name, |b| b.iter(|| strip_ansi(black_box(&input))));
+        group.bench_function(format!("{name}_bytes"), |b| {
+            b.iter(|| black_box(input.as_bytes()).non_esc_bytes().count())
+        });
+    }
+    // The same ASCII log, then with a char that needs decoding so none of it is taken as
+    // ASCII.
+    let ascii = log(10);
+    let decoded = format!("\u{2014}{ascii}");
+    group.throughput(Throughput::Bytes(ascii.len() as u64));
+    group.bench_function("ascii", |b| b.iter(|| strip_ansi(black_box(&ascii))));
+    group.bench_function("ascii_decoded", |b| {
+        b.iter(|| strip_ansi(black_box(&decoded)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
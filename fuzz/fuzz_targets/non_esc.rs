@@ -0,0 +1,63 @@
+//! Every input must be stripped in finite time, one item per byte at most, and the same
+//! whether it's stripped whole or pushed to a `StatefulStripper` in pieces.
+//!
+//! Run with `cargo +nightly fuzz run non_esc` from the crate root. A hang shows up as a
+//! libFuzzer timeout, and a stripper that yields without making progress fails the bound.
+
+#![no_main]
+
+use ansi_strip::{AnsiStripperBuilder, ControlMode, EscapeKind, NonEsc, NonEscBytes};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    assert!(data.non_esc_bytes().count() <= data.len());
+    let eight_bit = AnsiStripperBuilder::new().control_mode(ControlMode::EightBit);
+    assert!(eight_bit.from_bytes(data).count() <= data.len());
+
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    assert!(s.non_esc().count() <= s.len());
+    // Options only the iterators honour.
+    let builders = [
+        AnsiStripperBuilder::new().line_color_simplify(true),
+        AnsiStripperBuilder::new()
+            .osc8_as_markdown(true)
+            .lenient(true),
+    ];
+    for builder in builders {
+        // A final reset or link close can come after the last byte.
+        assert!(builder.build(s).count() <= s.len() + 3);
+    }
+
+    // Options a `StatefulStripper` honours too, so pushing `s` a few chars at a time must
+    // give what `strip` does, with the first byte picking how many.
+    let step = usize::from(data.first().map_or(0, |b| b % 8)) + 1;
+    let streamable = [
+        AnsiStripperBuilder::new()
+            .pad_removed(true)
+            .linux_console(true),
+        AnsiStripperBuilder::new()
+            .keep_sgr(true)
+            .preserve_hyperlinks(true),
+        AnsiStripperBuilder::new()
+            .esc_byte('~')
+            .keep_kinds(&[EscapeKind::Osc, EscapeKind::Other])
+            .csi_finals(&['m']),
+    ];
+    for builder in streamable {
+        assert!(builder.build(s).count() <= s.len());
+        let mut stateful = builder.stateful();
+        let mut out = String::new();
+        let mut start = 0;
+        while start < s.len() {
+            let mut end = (start + step).min(s.len());
+            while !s.is_char_boundary(end) {
+                end += 1;
+            }
+            out.push_str(&stateful.push(&s[start..end]));
+            start = end;
+        }
+        assert_eq!(builder.strip(s), out);
+    }
+});
@@ -0,0 +1,1117 @@
+#[cfg(feature = "alloc")]
+use core::fmt;
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use alloc::{borrow::Cow, string::String, string::ToString, sync::Arc, vec::Vec};
+
+use crate::kind::{after_introducer, hyperlink_uri};
+use crate::sgr::{is_sgr, is_sgr_reset};
+use crate::{AnsiByteStripper, AnsiStripper, ControlMode, CsiFinalRange, Segments};
+use crate::{EscapeKind, ESC};
+#[cfg(feature = "alloc")]
+use crate::{Span, StatefulStripper};
+
+/// DECTCEM hide and show cursor, after the CSI introducer.
+const CURSOR_VISIBILITY: [&[u8]; 2] = [b"?25l", b"?25h"];
+
+#[cfg(feature = "alloc")]
+type TextMapFn = dyn Fn(&str) -> Cow<'_, str> + Send + Sync;
+
+/// A caller's transform for kept text, shared by every stripper a builder creates.
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub(crate) struct TextMap(Arc<TextMapFn>);
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for TextMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TextMap(..)")
+    }
+}
+
+/// Cut each run of blank lines in the stripped text `s` down to at most `max`.
+///
+/// A blank line has nothing visible on it, only any sequences kept by `options` and maybe a
+/// `\r` before its `\n`. Those sequences stay when the line is cut, so the styling after it
+/// doesn't change.
+#[cfg(feature = "alloc")]
+fn squeeze_blank_lines(s: &str, max: usize, options: &Options) -> String {
+    // Only the sequences themselves matter here, not which of them were kept.
+    let plain = Options {
+        esc_byte: options.esc_byte,
+        control_mode: options.control_mode,
+        ..Options::default()
+    };
+    let mut out = String::with_capacity(s.len());
+    let mut run = 0;
+    for line in s.split_inclusive('\n') {
+        let body = line
+            .strip_suffix('\n')
+            .map(|l| l.strip_suffix('\r').unwrap_or(l));
+        match body {
+            Some(body)
+                if AnsiStripper::with_options(body, plain.clone())
+                    .next()
+                    .is_none() =>
+            {
+                run += 1;
+                if run > max {
+                    out.push_str(body);
+                    continue;
+                }
+            }
+            _ => run = 0,
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// Push the text run `text` onto `out`, cutting the whitespace before each line break.
+///
+/// A line's trailing whitespace can be split by kept escapes, so `trailing` holds where in
+/// `out` each piece of it is, run by run, until a line break or something visible.
+#[cfg(feature = "alloc")]
+fn push_trimmed(out: &mut String, text: &str, trailing: &mut Vec<Range<usize>>) {
+    for piece in text.split_inclusive('\n') {
+        let line = piece
+            .strip_suffix('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line));
+        let content = line.unwrap_or(piece);
+        let visible = content.trim_end().len();
+        if visible > 0 {
+            trailing.clear();
+        }
+        trailing.push(out.len() + visible..out.len() + content.len());
+        out.push_str(piece);
+        if line.is_some() {
+            cut_trailing(out, trailing);
+        }
+    }
+}
+
+/// Cut the whitespace `push_trimmed` noted from `out`, last first so the ranges stay put.
+#[cfg(feature = "alloc")]
+fn cut_trailing(out: &mut String, trailing: &mut Vec<Range<usize>>) {
+    for range in trailing.drain(..).rev() {
+        out.replace_range(range, "");
+    }
+}
+
+/// Settings shared by a builder and the strippers it creates.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Options {
+    #[cfg(feature = "alloc")]
+    keep_exact: Vec<String>,
+    pub(crate) pad_removed: bool,
+    pub(crate) linux_console: bool,
+    pub(crate) esc_byte: Option<u8>,
+    keep_sgr: bool,
+    keep_cursor_visibility: bool,
+    keep_window_ops: bool,
+    keep_kinds: u16,
+    #[cfg(feature = "alloc")]
+    csi_finals: Option<Vec<char>>,
+    #[cfg(feature = "alloc")]
+    pub(crate) osc52_extract: bool,
+    pub(crate) line_color_simplify: bool,
+    pub(crate) control_mode: Option<ControlMode>,
+    pub(crate) csi_final_range: CsiFinalRange,
+    #[cfg(feature = "alloc")]
+    pub(crate) truncate_on_unterminated: bool,
+    pub(crate) lenient: bool,
+    preserve_hyperlinks: bool,
+    pub(crate) osc8_as_markdown: bool,
+    pub(crate) group_adjacent_escapes: bool,
+    #[cfg(feature = "alloc")]
+    max_blank_lines: Option<usize>,
+    #[cfg(feature = "alloc")]
+    trim_trailing: bool,
+    #[cfg(feature = "alloc")]
+    map_text: Option<TextMap>,
+}
+
+impl Options {
+    /// Apply the `map_text` transform, if any, to a kept text run.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn map_text<'s>(&self, text: &'s str) -> Cow<'s, str> {
+        match &self.map_text {
+            Some(TextMap(f)) => f(text),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Does this SGR give a line its colour when simplifying line colours?
+    pub(crate) fn opens_line_color(&self, seq: &[u8]) -> bool {
+        is_sgr(seq, ESC as u8) && !is_sgr_reset(seq, ESC as u8)
+    }
+
+    /// The byte that stands for ESC.
+    pub(crate) fn esc(&self) -> u8 {
+        self.esc_byte.unwrap_or(ESC as u8)
+    }
+
+    /// Should this complete escape sequence be passed through instead of removed?
+    ///
+    /// Sequences are recognised by what follows the introducer, which may be `esc_byte`.
+    pub(crate) fn keeps(&self, seq: &[u8]) -> bool {
+        let esc = self.esc();
+        let kind = EscapeKind::of_bytes(seq, esc);
+        (self.keep_sgr && kind == Some(EscapeKind::Sgr))
+            || (self.keep_cursor_visibility
+                && after_introducer(seq, esc, b'[', 0x9b)
+                    .is_some_and(|body| CURSOR_VISIBILITY.contains(&body)))
+            || (self.keep_window_ops && kind == Some(EscapeKind::WindowOp))
+            || kind.is_some_and(|kind| self.keep_kinds & kind.bit() != 0)
+            || self.keeps_configured(seq)
+    }
+
+    /// Is this one of the `keep_exact` sequences, or a CSI outside `csi_finals`?
+    #[cfg(feature = "alloc")]
+    fn keeps_configured(&self, seq: &[u8]) -> bool {
+        self.keep_exact.iter().any(|k| k.as_bytes() == seq) || self.csi_final_unrecognised(seq)
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn keeps_configured(&self, _seq: &[u8]) -> bool {
+        false
+    }
+
+    /// Where the URI is in this removed sequence, if it's an OSC 8 hyperlink to keep one from.
+    pub(crate) fn preserved_uri(&self, seq: &[u8]) -> Option<Range<usize>> {
+        hyperlink_uri(seq).filter(|_| self.preserve_hyperlinks)
+    }
+
+    /// Is this a CSI whose final byte isn't in the configured set?
+    ///
+    /// The CSI may start with `ESC [`, with `esc_byte` in place of ESC, or with the C1 CSI.
+    #[cfg(feature = "alloc")]
+    fn csi_final_unrecognised(&self, seq: &[u8]) -> bool {
+        match (
+            &self.csi_finals,
+            after_introducer(seq, self.esc(), b'[', 0x9b).and_then(|s| s.last()),
+        ) {
+            (Some(finals), Some(&last)) => !finals.contains(&char::from(last)),
+            _ => false,
+        }
+    }
+}
+
+/// Configure an `AnsiStripper` before running it over a string slice.
+#[derive(Clone, Debug, Default)]
+pub struct AnsiStripperBuilder {
+    options: Options,
+}
+
+impl AnsiStripperBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep any escape sequence that exactly matches one of `seqs`, stripping all others.
+    #[cfg(feature = "alloc")]
+    pub fn keep_exact(mut self, seqs: &[&str]) -> Self {
+        self.options
+            .keep_exact
+            .extend(seqs.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Keep SGR (colour and style) sequences, stripping all others. An SGR introduced by the
+    /// C1 CSI, U+009B, is kept too.
+    ///
+    /// Private-prefixed sequences that also end in `m`, e.g. `ESC [ > 4 ; 2 m`, aren't SGR and
+    /// are still stripped.
+    pub fn keep_sgr(mut self, keep: bool) -> Self {
+        self.options.keep_sgr = keep;
+        self
+    }
+
+    /// Keep the hide and show cursor sequences (`ESC [ ? 25 l` and `ESC [ ? 25 h`), so a
+    /// viewer of the filtered output still hides the cursor under a spinner.
+    ///
+    /// Combine with `keep_exact` to keep any other sequences alongside these.
+    pub fn keep_cursor_visibility(mut self, keep: bool) -> Self {
+        self.options.keep_cursor_visibility = keep;
+        self
+    }
+
+    /// Keep window manipulation sequences, the CSIs ending in `t` that `EscapeKind::of` calls
+    /// `WindowOp`, such as `ESC [ 8 ; 24 ; 80 t` to resize the window.
+    pub fn keep_window_ops(mut self, keep: bool) -> Self {
+        self.options.keep_window_ops = keep;
+        self
+    }
+
+    /// Keep every sequence whose `EscapeKind` is one of `kinds`, stripping the rest.
+    ///
+    /// `&[EscapeKind::Sgr]` keeps colour but strips cursor movement, and `&[EscapeKind::Osc]`
+    /// keeps titles and hyperlinks but strips colour. This adds to what the other `keep_`
+    /// options keep. A sequence with a C1 introducer has no kind, so it's always stripped.
+    pub fn keep_kinds(mut self, kinds: &[EscapeKind]) -> Self {
+        self.options.keep_kinds = kinds.iter().fold(0, |set, kind| set | kind.bit());
+        self
+    }
+
+    /// Only strip CSI sequences ending in one of `finals`, passing any other CSI through as is.
+    #[cfg(feature = "alloc")]
+    pub fn csi_finals(mut self, finals: &[char]) -> Self {
+        self.options.csi_finals = Some(finals.to_vec());
+        self
+    }
+
+    /// Replace removed characters that occupied columns with spaces.
+    ///
+    /// Escape sequences are zero-width so they are still removed outright, but each tab is
+    /// replaced by the spaces needed to reach the next 8-column tab stop, counting columns
+    /// in visible chars since the last newline.
+    pub fn pad_removed(mut self, pad: bool) -> Self {
+        self.options.pad_removed = pad;
+        self
+    }
+
+    /// Recognise the Linux console's unterminated palette sequences.
+    ///
+    /// `ESC ] P nrrggbb` (exactly 7 hex digits) sets a palette entry and `ESC ] R` resets the
+    /// palette. Neither waits for BEL or ST, so without this they swallow the following text.
+    pub fn linux_console(mut self, linux: bool) -> Self {
+        self.options.linux_console = linux;
+        self
+    }
+
+    /// Start sequences with `esc` in place of ESC, which is then just text.
+    ///
+    /// For in-band formats that copy ANSI's structure with another lead byte: with
+    /// `esc_byte('~')`, `~[0m` is an SGR and `~]0;title~\` an OSC. The options that pick
+    /// out sequences to keep, such as `keep_sgr` and `keep_kinds`, look for `esc` too; the
+    /// hyperlink options and `line_color_simplify` still look for ESC.
+    ///
+    /// # Panics
+    ///
+    /// If `esc` isn't ASCII, as it wouldn't be one byte in a `&str`.
+    pub fn esc_byte(mut self, esc: char) -> Self {
+        assert!(esc.is_ascii(), "escape byte {esc:?} isn't ASCII");
+        self.options.esc_byte = Some(esc as u8);
+        self
+    }
+
+    /// Decode OSC 52 clipboard sequences for a `StatefulStripper`'s `on_clipboard` callback.
+    #[cfg(feature = "alloc")]
+    pub fn osc52_extract(mut self, extract: bool) -> Self {
+        self.options.osc52_extract = extract;
+        self
+    }
+
+    /// Whether the 8-bit C1 controls (U+009B for CSI, U+009D for OSC, and so on) introduce
+    /// sequences, or are left as text.
+    ///
+    /// By default they do for `&str` input but not for bytes, where 0x80 to 0x9f are also
+    /// UTF-8 continuation bytes. `detect_control_mode` can pick this from a sample.
+    pub fn control_mode(mut self, mode: ControlMode) -> Self {
+        self.options.control_mode = Some(mode);
+        self
+    }
+
+    /// Which chars end a CSI, `CsiFinalRange::Standard` (`@` to `~`) unless set.
+    ///
+    /// A CSI cut short by a char outside the range is malformed, so that char and what
+    /// follows are kept as text. This only changes where sequences end; `csi_finals` picks
+    /// which of them are stripped.
+    pub fn csi_final_range(mut self, range: CsiFinalRange) -> Self {
+        self.options.csi_final_range = range;
+        self
+    }
+
+    /// Drop a sequence left unterminated at the end of the input, along with anything after
+    /// its introducer, rather than holding on to it.
+    ///
+    /// The iterators always do this, as their input can't continue. A `StatefulStripper`
+    /// normally carries the sequence over to the next push; with this it discards it, so
+    /// each push's output is just the visible text before the sequence began.
+    #[cfg(feature = "alloc")]
+    pub fn truncate_on_unterminated(mut self, truncate: bool) -> Self {
+        self.options.truncate_on_unterminated = truncate;
+        self
+    }
+
+    /// Replace each OSC 8 hyperlink with its URI as plain text, stripping everything else.
+    ///
+    /// The link text that follows passes through as usual, and the closing `ESC ] 8 ; ; ST`,
+    /// having no URI, leaves nothing behind. An OSC 8 cut off by the end of the input is
+    /// removed whole, as any unterminated sequence is. Ignored with `line_color_simplify`.
+    pub fn preserve_hyperlinks(mut self, preserve: bool) -> Self {
+        self.options.preserve_hyperlinks = preserve;
+        self
+    }
+
+    /// Turn each OSC 8 hyperlink into a Markdown link, `[text](URI)`, stripping everything
+    /// else.
+    ///
+    /// Links are often reopened without being closed: a link opened while another is open
+    /// closes that one first, and one still open at the end of the input is closed there. A
+    /// close with no link open leaves nothing behind. Takes precedence over
+    /// `preserve_hyperlinks`; only the iterators and `strip` honour this, so it's ignored with
+    /// `line_color_simplify` and by a `StatefulStripper`.
+    pub fn osc8_as_markdown(mut self, markdown: bool) -> Self {
+        self.options.osc8_as_markdown = markdown;
+        self
+    }
+
+    /// Have `segments` yield back-to-back escape sequences as one `Escape` segment.
+    pub fn group_adjacent_escapes(mut self, group: bool) -> Self {
+        self.options.group_adjacent_escapes = group;
+        self
+    }
+
+    /// Squeeze each run of blank lines in the output of `strip` down to at most `max`.
+    ///
+    /// A blank line is one with nothing left on it after stripping, ended by `\n` or `\r\n`,
+    /// so `max_blank_lines(0)` removes them all. Sequences kept on a line that's removed, such
+    /// as a reset with `keep_sgr`, stay. Other ways of stripping ignore this.
+    #[cfg(feature = "alloc")]
+    pub fn max_blank_lines(mut self, max: usize) -> Self {
+        self.options.max_blank_lines = Some(max);
+        self
+    }
+
+    /// Cut the whitespace from the end of each line in the output of `strip`.
+    ///
+    /// Only visible text is cut, so with `keep_sgr` a reset after the spaces stays, as do
+    /// any sequences kept among them: `ESC [ 31 m hi   ESC [ 0 m` becomes
+    /// `ESC [ 31 m hi ESC [ 0 m`. A `\r` before the `\n` is kept. Other ways of stripping
+    /// ignore this.
+    #[cfg(feature = "alloc")]
+    pub fn trim_trailing(mut self, trim: bool) -> Self {
+        self.options.trim_trailing = trim;
+        self
+    }
+
+    /// Yield a sequence left unterminated at the end of the input as text, rather than drop it.
+    ///
+    /// For truncated data where losing bytes matters more than a stray `ESC [`. A
+    /// `StatefulStripper` ignores this, as its input can always continue.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.options.lenient = lenient;
+        self
+    }
+
+    /// Give each line at most one colour: its first SGR, closed by a reset before the newline.
+    ///
+    /// Every later SGR on the line is stripped, as are lone resets, so each line of output
+    /// starts and ends in the default style. Takes precedence over `keep_sgr`. Only the
+    /// iterators honour this; a `StatefulStripper` ignores it.
+    pub fn line_color_simplify(mut self, simplify: bool) -> Self {
+        self.options.line_color_simplify = simplify;
+        self
+    }
+
+    /// Transform each kept run of visible text with `f`; kept escapes are left alone.
+    ///
+    /// Applies wherever the output is built as a `String`: `strip` and
+    /// `StatefulStripper::push`. The iterators yield borrowed slices, so they can't.
+    #[cfg(feature = "alloc")]
+    pub fn map_text<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> Cow<'_, str> + Send + Sync + 'static,
+    {
+        self.options.map_text = Some(TextMap(Arc::new(f)));
+        self
+    }
+
+    /// Strip `src` using these settings, collecting the output into a `String`.
+    #[cfg(feature = "alloc")]
+    pub fn strip(&self, src: &str) -> String {
+        let mut stripper = self.build(src);
+        let mut out = String::with_capacity(src.len());
+        let mut trailing = Vec::new();
+        while let Some(span) = stripper.next_kept() {
+            match span {
+                Span::Text(s) if self.options.trim_trailing => {
+                    push_trimmed(&mut out, &stripper.options.map_text(s), &mut trailing)
+                }
+                Span::Text(s) => out.push_str(&stripper.options.map_text(s)),
+                Span::Escape(seq) => out.push_str(seq),
+            }
+        }
+        cut_trailing(&mut out, &mut trailing);
+        match self.options.max_blank_lines {
+            Some(max) => squeeze_blank_lines(&out, max, &self.options),
+            None => out,
+        }
+    }
+
+    /// Create a stripper over `src` using these settings.
+    pub fn build<'a>(&self, src: &'a str) -> AnsiStripper<'a> {
+        AnsiStripper::with_options(src, self.options.clone())
+    }
+
+    /// Create a stripper over bytes, which needn't be UTF-8, using these settings.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_bytes<'a>(&self, src: &'a [u8]) -> AnsiByteStripper<'a> {
+        AnsiStripper::with_options(src, self.options.clone())
+    }
+
+    /// Split `src` into its text and escape segments using these settings.
+    ///
+    /// Every segment is a slice of `src`, so `pad_removed` doesn't apply, and nothing is
+    /// kept or transformed: kept sequences are `Escape` segments like the rest.
+    pub fn segments<'a>(&self, src: &'a str) -> Segments<'a> {
+        let mut options = self.options.clone();
+        options.pad_removed = false;
+        Segments::new(src, options)
+    }
+
+    /// `segments` for bytes, which needn't be UTF-8.
+    pub fn byte_segments<'a>(&self, src: &'a [u8]) -> Segments<'a, [u8]> {
+        let mut options = self.options.clone();
+        options.pad_removed = false;
+        Segments::new(src, options)
+    }
+
+    /// Create a stripper for input that arrives in chunks, using these settings.
+    #[cfg(feature = "alloc")]
+    pub fn stateful<'f>(&self) -> StatefulStripper<'f> {
+        StatefulStripper::with_options(self.options.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use crate::Mode;
+    use crate::Segment;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_exact_osc() {
+        let mark = "\x1b]633;A\x07";
+        let sample = format!("{mark}\x1b[1m$\x1b[0m ls\x1b]0;title\x07{mark}\x1b]633;B\x07");
+        let actual: String = AnsiStripperBuilder::new()
+            .keep_exact(&[mark])
+            .build(&sample)
+            .collect();
+        assert_eq!(format!("{mark}$ ls{mark}"), actual);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_exact_is_not_a_prefix_match() {
+        let actual: String = AnsiStripperBuilder::new()
+            .keep_exact(&["\x1b[1m"])
+            .build("a\x1b[1;2mb\x1b[1mc")
+            .collect();
+        assert_eq!("ab\x1b[1mc", actual);
+    }
+
+    fn padded(sample: &str) -> String {
+        AnsiStripperBuilder::new()
+            .pad_removed(true)
+            .build(sample)
+            .collect()
+    }
+
+    #[test]
+    fn pad_tabs_to_tab_stops() {
+        assert_eq!("        x", padded("\tx"));
+        assert_eq!("a       b", padded("a\tb"));
+        assert_eq!("abcdefgh        i", padded("abcdefgh\ti"));
+        assert_eq!("a               b", padded("a\t\tb"));
+    }
+
+    #[test]
+    fn pad_ignores_escapes() {
+        assert_eq!("ab      c", padded("\x1b[31mab\x1b[0m\tc"));
+        assert_eq!("ab", padded("\x1b[31ma\x1b[0mb"));
+    }
+
+    #[test]
+    fn pad_restarts_columns_after_newline() {
+        assert_eq!("abc\n        x", padded("abc\n\tx"));
+        assert_eq!("abc\nd       x", padded("abc\nd\tx"));
+    }
+
+    #[test]
+    fn tabs_kept_by_default() {
+        let actual: String = AnsiStripperBuilder::new().build("a\tb").collect();
+        assert_eq!("a\tb", actual);
+    }
+
+    fn linux(sample: &str) -> String {
+        AnsiStripperBuilder::new()
+            .linux_console(true)
+            .build(sample)
+            .collect()
+    }
+
+    #[test]
+    fn linux_palette_set() {
+        assert_eq!("x", linux("\x1b]P1ff0000x"));
+        assert_eq!("ab", linux("a\x1b]PFAbCdEfb"));
+    }
+
+    #[test]
+    fn linux_palette_reset() {
+        assert_eq!("x", linux("\x1b]Rx"));
+    }
+
+    #[test]
+    fn linux_palette_malformed() {
+        assert_eq!("zx", linux("\x1b]P12zx"));
+        assert_eq!("x", linux("\x1b]P12\x1b[0mx"));
+        assert_eq!("", linux("\x1b]P12"));
+    }
+
+    #[test]
+    fn linux_console_keeps_standard_osc() {
+        assert_eq!("x", linux("\x1b]0;Ptitle\x07x"));
+        assert_eq!("x", linux("\x1b]0;R\x1b\\x"));
+    }
+
+    #[test]
+    fn linux_palette_needs_option() {
+        let actual: String = AnsiStripperBuilder::new().build("\x1b]Rx").collect();
+        assert_eq!("", actual);
+    }
+
+    #[test]
+    fn keep_sgr() {
+        let actual: String = AnsiStripperBuilder::new()
+            .keep_sgr(true)
+            .build("\x1b[2J\x1b[31mred\x1b[0m\x1b]0;t\x07")
+            .collect();
+        assert_eq!("\x1b[31mred\x1b[0m", actual);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_sgr_strips_other_controls() {
+        let sample = "\x1b[31m\x1b[2Jred\x1bP1$r0m\x1b\\\x1b[3;1H\u{9b}2J\x1b[0m\x1b(B";
+        let actual = AnsiStripperBuilder::new().keep_sgr(true).strip(sample);
+        assert_eq!("\x1b[31mred\x1b[0m", actual);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_cursor_visibility() {
+        let sample = "\x1b[?25l\x1b[2J\x1b[31m|\x1b[0m\x1b[1D/\x1b[?25h\x1b[?25;1h";
+        let builder = AnsiStripperBuilder::new().keep_cursor_visibility(true);
+        assert_eq!("\x1b[?25l|/\x1b[?25h", builder.strip(sample));
+        let with_extra = builder.keep_exact(&["\x1b[1D"]).strip(sample);
+        assert_eq!("\x1b[?25l|\x1b[1D/\x1b[?25h", with_extra);
+        assert_eq!("|/", AnsiStripperBuilder::new().strip(sample));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_window_ops() {
+        let sample = "\x1b[22;0t\x1b[8;24;80t\x1b[1mtext\x1b[0m\x1b[5 t\x1b[23;0t";
+        assert_eq!("text", AnsiStripperBuilder::new().strip(sample));
+        assert_eq!(
+            "\x1b[22;0t\x1b[8;24;80ttext\x1b[23;0t",
+            AnsiStripperBuilder::new()
+                .keep_window_ops(true)
+                .strip(sample)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_kinds() {
+        let sample =
+            "\x1b[2J\x1b[31mred\x1b[0m \x1b]8;;https://x\x07link\x1b]8;;\x07\x1b(B\x1b[5;1H";
+        let sgr = AnsiStripperBuilder::new().keep_kinds(&[EscapeKind::Sgr]);
+        assert_eq!("\x1b[31mred\x1b[0m link", sgr.strip(sample));
+        let osc = AnsiStripperBuilder::new().keep_kinds(&[EscapeKind::Osc]);
+        assert_eq!(
+            "red \x1b]8;;https://x\x07link\x1b]8;;\x07",
+            osc.strip(sample)
+        );
+        let both = AnsiStripperBuilder::new().keep_kinds(&[EscapeKind::Other, EscapeKind::Csi]);
+        assert_eq!("\x1b[2Jred link\x1b(B\x1b[5;1H", both.strip(sample));
+        assert_eq!("red", sgr.keep_kinds(&[]).strip("\x1b[31mred"));
+        assert_eq!("\u{9d}0;t\u{9c}red", osc.strip("\u{9d}0;t\u{9c}red"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn keep_c1_csi() {
+        let sample = "\u{9b}1;31mred\u{9b}0m\u{9b}2J\u{9b}22;0t\u{9b}?25l";
+        let sgr = AnsiStripperBuilder::new().keep_sgr(true);
+        assert_eq!("\u{9b}1;31mred\u{9b}0m", sgr.strip(sample));
+        let kinds = AnsiStripperBuilder::new().keep_kinds(&[EscapeKind::Sgr]);
+        assert_eq!("\u{9b}1;31mred\u{9b}0m", kinds.strip(sample));
+        let window_ops = AnsiStripperBuilder::new().keep_window_ops(true);
+        assert_eq!("red\u{9b}22;0t", window_ops.strip(sample));
+        // With 7-bit controls, U+009B isn't an introducer and there's nothing to keep.
+        let seven_bit = sgr.control_mode(ControlMode::SevenBit);
+        assert_eq!("\u{9b}1;31mred", seven_bit.strip("\u{9b}1;31mred"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn seven_bit_leaves_c1_as_text() {
+        let builder = AnsiStripperBuilder::new().control_mode(ControlMode::SevenBit);
+        assert_eq!("a\u{9b}0mb", builder.strip("a\u{9b}0m\x1b[1mb"));
+    }
+
+    #[test]
+    fn bytes_default_to_seven_bit() {
+        let strip = |builder: AnsiStripperBuilder, src: &[u8]| -> Vec<u8> {
+            builder.from_bytes(src).flatten().copied().collect()
+        };
+        // Û is 0xc3 0x9b in UTF-8.
+        let utf8 = "Û\x1b[1mÛ".as_bytes();
+        assert_eq!("ÛÛ".as_bytes(), strip(AnsiStripperBuilder::new(), utf8));
+        assert_eq!(
+            b"a\x9b0mb",
+            &strip(AnsiStripperBuilder::new(), b"a\x9b0mb")[..]
+        );
+        let eight_bit = AnsiStripperBuilder::new().control_mode(ControlMode::EightBit);
+        assert_eq!(b"ab", &strip(eight_bit, b"a\x9b0m\x9d0;t\x9cb")[..]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn esc_byte() {
+        let tilde = AnsiStripperBuilder::new().esc_byte('~');
+        assert_eq!("ab", tilde.strip("a~[0mb"));
+        assert_eq!("a\x1b[0mb", tilde.strip("a\x1b[0mb"));
+        assert_eq!("xy", tilde.strip("x~]0;title~\\y~Pq#0~\\"));
+        let bytes: Vec<u8> = tilde.from_bytes(b"a~[1mb").flatten().copied().collect();
+        assert_eq!(b"ab", &bytes[..]);
+        let linux = tilde.clone().linux_console(true);
+        assert_eq!("ab", linux.strip("a~]P0ffffffb"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn esc_byte_keep_options() {
+        let sample = "~[2J~[1mbold~[0m~]0;t~\\~[?25l~[8;24;80t";
+        let tilde = AnsiStripperBuilder::new().esc_byte('~');
+        let sgr = tilde.clone().keep_sgr(true);
+        assert_eq!("~[1mbold~[0m", sgr.strip(sample));
+        // ESC is just text now, so there's no sequence to keep.
+        assert_eq!("\x1b[1mx", sgr.strip("\x1b[1mx"));
+        let kinds = tilde
+            .clone()
+            .keep_kinds(&[EscapeKind::Csi, EscapeKind::Osc]);
+        assert_eq!("~[2Jbold~]0;t~\\~[?25l", kinds.strip(sample));
+        let cursor = tilde.clone().keep_cursor_visibility(true);
+        assert_eq!("bold~[?25l", cursor.strip(sample));
+        let window_ops = tilde.keep_window_ops(true);
+        assert_eq!("bold~[8;24;80t", window_ops.strip(sample));
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't ASCII")]
+    fn esc_byte_not_ascii() {
+        AnsiStripperBuilder::new().esc_byte('é');
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn truncate_on_unterminated() {
+        let builder = AnsiStripperBuilder::new().truncate_on_unterminated(true);
+        assert_eq!("ab", builder.strip("ab\x1b]0;unterm"));
+        assert_eq!("ab", builder.strip("ab\x1b[1"));
+        assert_eq!("ab", builder.strip("a\x1b[0mb\x1bPq\nmore\n"));
+
+        let mut stripper = builder.stateful();
+        assert_eq!("ab", stripper.push("ab\x1b]0;unterm"));
+        assert_eq!("title\x07c", stripper.push("title\x07c"));
+        assert_eq!("ab", stripper.push("ab\x1b[1"));
+        assert_eq!((Mode::Normal, String::new()), stripper.into_parts());
+    }
+
+    #[test]
+    fn keep_sgr_strips_private_prefix_m() {
+        let actual: String = AnsiStripperBuilder::new()
+            .keep_sgr(true)
+            .build("\x1b[>4;2m\x1b[1mx\x1b[=0m")
+            .collect();
+        assert_eq!("\x1b[1mx", actual);
+    }
+
+    #[test]
+    fn bytes_keep_sgr_in_invalid_utf8() {
+        let sample = b"\xff\x1b[1m\xfe\x1b[2J\x1b[0m\xc3";
+        let actual: Vec<u8> = AnsiStripperBuilder::new()
+            .keep_sgr(true)
+            .from_bytes(sample)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(b"\xff\x1b[1m\xfe\x1b[0m\xc3".to_vec(), actual);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn bytes_share_options() {
+        let builder = AnsiStripperBuilder::new()
+            .keep_exact(&["\x1b]633;A\x07"])
+            .pad_removed(true)
+            .linux_console(true);
+        let sample = "\x1b]633;A\x07a\tb\x1b]Rc\x1b]0;t\x07";
+        let from_str: String = builder.build(sample).collect();
+        let from_bytes: Vec<u8> = builder
+            .from_bytes(sample.as_bytes())
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!("\x1b]633;A\x07a       bc", from_str);
+        assert_eq!(from_str.as_bytes(), from_bytes);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn csi_finals_only_sgr() {
+        let builder = AnsiStripperBuilder::new().csi_finals(&['m']);
+        assert_eq!("", builder.build("\x1b[0m").collect::<String>());
+        assert_eq!("\x1b[2J", builder.build("\x1b[2J").collect::<String>());
+        assert_eq!(
+            "a\x1b[2Jbc\x1b[1;1H",
+            builder
+                .build("a\x1b[2J\x1b[31mb\x1b]0;t\x07c\x1b[1;1H")
+                .collect::<String>()
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn csi_finals_other_introducers() {
+        let c1 = AnsiStripperBuilder::new().csi_finals(&['m']);
+        assert_eq!("ab\u{9b}2Jc", c1.strip("a\u{9b}1mb\u{9b}2Jc"));
+        let sub = AnsiStripperBuilder::new().esc_byte('~').csi_finals(&['m']);
+        assert_eq!("ab~[2Jc", sub.strip("a~[1mb~[2Jc"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn csi_finals_several() {
+        let builder = AnsiStripperBuilder::new().csi_finals(&['m', 'K']);
+        assert_eq!(
+            "ab\x1b[2J",
+            builder.build("a\x1b[Kb\x1b[2J\x1b[m").collect::<String>()
+        );
+    }
+
+    #[test]
+    fn csi_final_range() {
+        let strip = |range, s| -> String {
+            AnsiStripperBuilder::new()
+                .csi_final_range(range)
+                .build(s)
+                .collect()
+        };
+        let sample = "a\x1b[1@b\x1b[2~c\x1b[2 qd\x1b[1\u{2192}e";
+        assert_eq!("abcd\u{2192}e", strip(CsiFinalRange::Standard, sample));
+        assert_eq!("ab~cqd\u{2192}e", strip(CsiFinalRange::Strict, sample));
+        assert_eq!("abcde", strip(CsiFinalRange::Permissive, sample));
+        assert_eq!("a@bcde", strip(CsiFinalRange::Custom(|c| c != '@'), sample));
+        // Still never a control, nor a parameter after an intermediate.
+        assert_eq!("\x7fx", strip(CsiFinalRange::Permissive, "\x1b[1\x7fx"));
+        assert_eq!("1x", strip(CsiFinalRange::Permissive, "\x1b[ 1x"));
+        assert_eq!(
+            vec![&b"a"[..], b"\xc3\xa9"],
+            AnsiStripperBuilder::new()
+                .csi_final_range(CsiFinalRange::Strict)
+                .from_bytes(b"\x1b[1ma\x1b[1\xc3\xa9")
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn default_strips_everything() {
+        let actual: String = AnsiStripperBuilder::new().build("a\x1b[1mb").collect();
+        assert_eq!("ab", actual);
+    }
+
+    fn simplified(sample: &str) -> String {
+        AnsiStripperBuilder::new()
+            .line_color_simplify(true)
+            .build(sample)
+            .collect()
+    }
+
+    #[test]
+    fn line_color_keeps_first_sgr_and_resets() {
+        assert_eq!(
+            "\x1b[31mredgreenbold\x1b[0m\nplain\n",
+            simplified("\x1b[31mred\x1b[32mgreen\x1b[1mbold\x1b[0m\nplain\n")
+        );
+    }
+
+    #[test]
+    fn line_color_each_line_bounded() {
+        assert_eq!(
+            "\x1b[31ma\x1b[0m\nb\x1b[32mc\x1b[0m\n\n",
+            simplified("\x1b[31ma\nb\x1b[32mc\x1b[1m\n\n")
+        );
+        // Ending mid-line still closes the colour.
+        assert_eq!("\x1b[1mab\x1b[0m", simplified("\x1b[1ma\x1b[2J\x1b[32mb"));
+    }
+
+    #[test]
+    fn line_color_ignores_lone_resets() {
+        assert_eq!("plain\n", simplified("plain\x1b[0m\x1b[m\n"));
+        assert_eq!(
+            "a\x1b[4mb\x1b[0m\n",
+            simplified("a\x1b[0m\x1b[4mb\x1b[0m\n")
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    fn upper(s: &str) -> Cow<'_, str> {
+        Cow::Owned(s.to_uppercase())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn map_text_uppercases() {
+        let builder = AnsiStripperBuilder::new().map_text(upper);
+        assert_eq!(
+            "HELLO, WORLD!",
+            builder.strip("\x1b[1mHello\x1b[0m, world!")
+        );
+        assert_eq!("", builder.strip("\x1b[2J"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn map_text_leaves_kept_escapes() {
+        let builder = AnsiStripperBuilder::new().keep_sgr(true).map_text(upper);
+        assert_eq!(
+            "\x1b[31mRED\x1b[0m PLAIN",
+            builder.strip("\x1b[31mred\x1b[0m\x1b]0;title\x07 plain")
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn map_text_in_stateful() {
+        let mut stripper = AnsiStripperBuilder::new()
+            .map_text(|s| Cow::Owned(s.replace("secret", "******")))
+            .stateful();
+        assert_eq!("a ******", stripper.push("a \x1b[1msecret\x1b["));
+        assert_eq!(" b", stripper.push("0m b"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn strip_without_map_matches_iterator() {
+        let builder = AnsiStripperBuilder::new().line_color_simplify(true);
+        let sample = "\x1b[31ma\x1b[32mb\nc";
+        assert_eq!(
+            builder.build(sample).collect::<String>(),
+            builder.strip(sample)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn preserve_hyperlinks() {
+        let builder = AnsiStripperBuilder::new().preserve_hyperlinks(true);
+        let linked = "see \x1b]8;id=7;https://example.com\x1b\\\x1b[1mdocs\x1b[0m\x1b]8;;\x1b\\.";
+        assert_eq!("see https://example.comdocs.", builder.strip(linked));
+        assert_eq!("see docs.", AnsiStripperBuilder::new().strip(linked));
+        assert_eq!("a\x07b", builder.strip("a\x1b]8;;\x07\x07b"));
+        assert_eq!("ab", builder.strip("a\x1b]0;title\x07b"));
+        assert_eq!("a", builder.strip("a\x1b]8;;https://cut.off"));
+
+        let mut stateful = builder.stateful();
+        assert_eq!("see ", stateful.push(&linked[..10]));
+        assert_eq!("https://example.comdocs.", stateful.push(&linked[10..]));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn osc8_as_markdown() {
+        let builder = AnsiStripperBuilder::new().osc8_as_markdown(true);
+        let linked = "see \x1b]8;id=7;https://example.com\x1b\\\x1b[1mdocs\x1b[0m\x1b]8;;\x1b\\.";
+        assert_eq!("see [docs](https://example.com).", builder.strip(linked));
+        assert_eq!(
+            vec!["see ", "[", "docs", "](", "https://example.com", ")", "."],
+            builder.build(linked).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "[](https://a)b",
+            builder.strip("\x1b]8;;https://a\x07\x1b]8;;\x07b")
+        );
+        assert_eq!("ab", builder.strip("a\x1b]8;;\x07b\x1b]8;;\x1b\\"));
+        let both = builder.clone().preserve_hyperlinks(true);
+        assert_eq!("see [docs](https://example.com).", both.strip(linked));
+        let upper = builder.clone().map_text(|s| s.to_uppercase().into());
+        assert_eq!("SEE [DOCS](https://example.com).", upper.strip(linked));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn c1_hyperlinks() {
+        for linked in [
+            "\x1b]8;;http://x\u{9c}link\x1b]8;;\u{9c}",
+            "\u{9d}8;;http://x\x07link\u{9d}8;;\x1b\\",
+        ] {
+            let markdown = AnsiStripperBuilder::new().osc8_as_markdown(true);
+            assert_eq!("[link](http://x)", markdown.strip(linked), "{linked:?}");
+            let preserved = AnsiStripperBuilder::new().preserve_hyperlinks(true);
+            assert_eq!("http://xlink", preserved.strip(linked), "{linked:?}");
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn osc8_reopened_without_close() {
+        let reopened = "\x1b]8;;https://one\x1b\\text1 \x1b]8;;https://two\x1b\\text2";
+        assert_eq!("text1 text2", AnsiStripperBuilder::new().strip(reopened));
+
+        let builder = AnsiStripperBuilder::new().osc8_as_markdown(true);
+        // The first link closes where the second opens, and the second at the end.
+        assert_eq!(
+            "[text1 ](https://one)[text2](https://two)",
+            builder.strip(reopened)
+        );
+        let closed = format!("{reopened}\x1b]8;;\x1b\\!");
+        assert_eq!(
+            "[text1 ](https://one)[text2](https://two)!",
+            builder.strip(&closed)
+        );
+        let bytes: Vec<u8> = builder
+            .from_bytes(closed.as_bytes())
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(builder.strip(&closed).as_bytes(), &bytes[..]);
+        let mut stripper = builder.build(&closed);
+        stripper.by_ref().for_each(drop);
+        assert_eq!(3, stripper.escape_count());
+    }
+
+    #[test]
+    fn group_adjacent_escapes() {
+        let src = "\x1b[0m\x1b[1mbold\x1b]0;t\x07\x1b\n";
+        let segments = |builder: AnsiStripperBuilder| builder.segments(src).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Segment::Escape("\x1b[0m"),
+                Segment::Escape("\x1b[1m"),
+                Segment::Text("bold"),
+                Segment::Escape("\x1b]0;t\x07"),
+                Segment::Escape("\x1b"),
+                Segment::Text("\n"),
+            ],
+            segments(AnsiStripperBuilder::new())
+        );
+        assert_eq!(
+            vec![
+                Segment::Escape("\x1b[0m\x1b[1m"),
+                Segment::Text("bold"),
+                Segment::Escape("\x1b]0;t\x07\x1b"),
+                Segment::Text("\n"),
+            ],
+            segments(AnsiStripperBuilder::new().group_adjacent_escapes(true))
+        );
+        let tab: Vec<_> = AnsiStripperBuilder::new()
+            .pad_removed(true)
+            .segments("\t")
+            .collect();
+        assert_eq!(vec![Segment::Text("\t")], tab);
+    }
+
+    #[test]
+    fn byte_segments() {
+        let src = b"\xffa\x1b[1m\x1b]0;\xfe\x07b\x1b[";
+        let segments: Vec<_> = AnsiStripperBuilder::new().byte_segments(src).collect();
+        assert_eq!(
+            vec![
+                Segment::Text(&b"\xffa"[..]),
+                Segment::Escape(b"\x1b[1m"),
+                Segment::Escape(b"\x1b]0;\xfe\x07"),
+                Segment::Text(b"b"),
+                Segment::Escape(b"\x1b["),
+            ],
+            segments
+        );
+        let grouped = AnsiStripperBuilder::new()
+            .group_adjacent_escapes(true)
+            .byte_segments(b"\x1b[1m\x1b[2mx")
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![Segment::Escape(&b"\x1b[1m\x1b[2m"[..]), Segment::Text(b"x")],
+            grouped
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn max_blank_lines() {
+        let src = "a\n\x1b[1m\n\n\x1b[0m\n\nb\n\nc\n";
+        assert_eq!(
+            "a\n\nb\n\nc\n",
+            AnsiStripperBuilder::new().max_blank_lines(1).strip(src)
+        );
+        assert_eq!(
+            "a\nb\nc\n",
+            AnsiStripperBuilder::new().max_blank_lines(0).strip(src)
+        );
+        assert_eq!("a\n\n\n\n\nb\n\nc\n", AnsiStripperBuilder::new().strip(src));
+
+        let crlf = "a\r\n\r\n\x1b[2K\r\n\r\nb\r\n";
+        let one = AnsiStripperBuilder::new().max_blank_lines(1);
+        assert_eq!("a\r\n\r\nb\r\n", one.strip(crlf));
+        // A line that has only a kept reset on it is still blank, and the reset isn't lost.
+        let sgr = one.clone().keep_sgr(true);
+        assert_eq!(
+            "\x1b[1ma\n\x1b[0m\nb\n",
+            sgr.strip("\x1b[1ma\n\x1b[0m\n\n\nb\n")
+        );
+        let none = sgr.max_blank_lines(0);
+        assert_eq!("a\n\x1b[0mb\n", none.strip("a\n\x1b[0m\n\r\nb\n"));
+        // Only lines ended by a newline count, and one with just spaces isn't blank.
+        assert_eq!("\n \n\n", one.strip("\n\n \n\n\n\x1b[0m"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn trim_trailing() {
+        let sgr = AnsiStripperBuilder::new()
+            .keep_sgr(true)
+            .trim_trailing(true);
+        assert_eq!("\x1b[31mhi\x1b[0m", sgr.strip("\x1b[31mhi   \x1b[0m"));
+        assert_eq!(
+            "\x1b[1ma\x1b[2m\x1b[0m\r\nb\x1b[0m\n\x1b[1m\x1b[0m",
+            sgr.strip("\x1b[1ma \x1b[2m\t\x1b[0m \r\nb \x1b]0;t\x07 \x1b[0m\n\x1b[1m  \x1b[0m")
+        );
+        assert_eq!("a \x1b[1m b", sgr.strip("a \x1b[1m b "));
+        let plain = AnsiStripperBuilder::new().trim_trailing(true);
+        assert_eq!("x\n\ny", plain.strip("x  \x1b[0m\n \t\ny\x1b[K  "));
+        let squeezed = plain.max_blank_lines(0);
+        assert_eq!("x\ny", squeezed.strip("x \n  \n\ny"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn lenient() {
+        let lenient = AnsiStripperBuilder::new().lenient(true);
+        assert_eq!(
+            vec!["abc", "\x1b["],
+            lenient.build("abc\x1b[").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["abc"],
+            AnsiStripperBuilder::new()
+                .build("abc\x1b[")
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("a\x1b]0;cut", lenient.strip("\x1b[1ma\x1b]0;cut"));
+        assert_eq!(
+            vec![&b"\xff"[..], b"\x1bP"],
+            lenient.from_bytes(b"\xff\x1bP").collect::<Vec<_>>()
+        );
+
+        let mut stateful = lenient.stateful();
+        assert_eq!("abc", stateful.push("abc\x1b["));
+        assert_eq!("d", stateful.push("0md"));
+    }
+}
@@ -0,0 +1,58 @@
+//! Stripping logs written in a legacy encoding, with `encoding_rs`.
+
+use alloc::{string::String, vec::Vec};
+
+use encoding_rs::Encoding;
+
+use crate::{strip_ansi, strip_bytes_into};
+
+/// Decode `bytes` as `encoding` and strip escape sequences from the result.
+///
+/// For ASCII-compatible encodings the escapes are stripped from the raw bytes before
+/// decoding, so only `ESC` introduces a sequence and bytes in the C1 range mean whatever the
+/// encoding says: `0x9b` is `›` in windows-1252, not a CSI. Note that WHATWG, and so
+/// `encoding_rs`, treats the ISO-8859-1 label as windows-1252. Other encodings, such as
+/// UTF-16, are decoded first and the text is stripped after.
+///
+/// Malformed input decodes to U+FFFD and no byte order mark is sniffed.
+pub fn strip_decode(bytes: &[u8], encoding: &'static Encoding) -> String {
+    if !encoding.is_ascii_compatible() {
+        let (text, _) = encoding.decode_without_bom_handling(bytes);
+        return strip_ansi(&text);
+    }
+    let mut kept = Vec::with_capacity(bytes.len());
+    strip_bytes_into(bytes, &mut kept);
+    let (text, _) = encoding.decode_without_bom_handling(&kept);
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, UTF_16LE, WINDOWS_1252};
+
+    #[test]
+    fn latin1_log() {
+        let log = b"caf\xe9 \x1b[1m\xabbold\xbb\x1b[0m\x1b]0;t\xeatle\x07 ok\n";
+        assert_eq!("café «bold» ok\n", strip_decode(log, WINDOWS_1252));
+        let by_label = Encoding::for_label(b"latin1").unwrap();
+        assert_eq!("café «bold» ok\n", strip_decode(log, by_label));
+    }
+
+    #[test]
+    fn c1_bytes_follow_the_encoding() {
+        assert_eq!("a›1mb", strip_decode(b"a\x9b1mb", WINDOWS_1252));
+        assert_eq!("a›b", strip_decode(b"a\x1b[1m\x9bb", WINDOWS_1252));
+    }
+
+    #[test]
+    fn multibyte_encodings() {
+        // 漢 in Shift_JIS is 0x8a 0xbf.
+        assert_eq!("漢字", strip_decode(b"\x8a\xbf\x1b[31m\x8e\x9a", SHIFT_JIS));
+        let utf16: Vec<u8> = "a\x1b[1mb"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!("ab", strip_decode(&utf16, UTF_16LE));
+    }
+}
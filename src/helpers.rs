@@ -0,0 +1,844 @@
+//! One-call conveniences built on top of `AnsiStripper`.
+
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+#[cfg(feature = "alloc")]
+use crate::{
+    strip_ansi, styled_segments, transition, Action, NonEscBytes, Segment, Segmented, Sgr,
+    StatefulStripper,
+};
+use crate::{EscOnly, EscapeKind, Mode, NonEsc, Span};
+
+/// True if stripping `s` would leave nothing, i.e. it holds only escape sequences.
+pub fn is_visibly_empty(s: &str) -> bool {
+    s.non_esc().next().is_none()
+}
+
+/// CSI finals that move the cursor along the line or repeat a char: CUF, CUB, CHA, CHT, CBT,
+/// HPA, HPR and REP.
+const HORIZONTAL_FINALS: &[u8] = b"CDGIZ`ab";
+
+/// True if the stripped text of `s` is as wide as `s` is when displayed.
+///
+/// Most escapes take up no room, so removing them changes nothing. The ones that do move
+/// text along the line, such as `ESC [ I` (forward a tab stop) or `ESC [ 5 C`, make this
+/// false, as the text after them would shift left once they're gone. So does any
+/// width-bearing control char in the visible text: a tab, a backspace, or a `\r` that isn't
+/// part of a `\r\n`, whose width depends on where the cursor is rather than on the text.
+pub fn strip_preserves_width(s: &str) -> bool {
+    let mut visible = s.non_esc().flat_map(str::chars).peekable();
+    while let Some(c) = visible.next() {
+        if matches!(c, '\t' | '\x08') || (c == '\r' && visible.peek() != Some(&'\n')) {
+            return false;
+        }
+    }
+    !s.esc_only().any(|seq| {
+        let params = seq
+            .strip_prefix("\x1b[")
+            .or_else(|| seq.strip_prefix('\u{9b}'));
+        matches!(params.and_then(|p| p.bytes().last()), Some(f) if HORIZONTAL_FINALS.contains(&f))
+    })
+}
+
+/// The number of complete escape sequences in `s`.
+pub fn escape_count(s: &str) -> usize {
+    let mut stripper = s.non_esc();
+    stripper.by_ref().for_each(drop);
+    stripper.escape_count()
+}
+
+/// What stripping some input removed, as counted by `count_escapes`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct StripStats {
+    /// Bytes of visible text, which stripping keeps.
+    pub text_bytes: usize,
+    /// Bytes removed: complete sequences, and any malformed or unterminated ones.
+    pub escape_bytes: usize,
+    /// Complete sequences removed.
+    pub escape_count: usize,
+    by_kind: [usize; 9],
+}
+
+impl StripStats {
+    /// How many of the complete sequences were of `kind`.
+    pub fn sequences_by_kind(&self, kind: EscapeKind) -> usize {
+        self.by_kind[kind as usize]
+    }
+}
+
+/// Count what stripping `s` would remove, in one pass and without building the text.
+///
+/// `text_bytes` and `escape_bytes` always add up to `s.len()`.
+pub fn count_escapes(s: &str) -> StripStats {
+    let mut stats = StripStats::default();
+    let mut stripper = s.non_esc();
+    while let Some(span) = stripper.next_span() {
+        match span {
+            Span::Text(run) => stats.text_bytes += run.len(),
+            Span::Escape(seq) => {
+                stats.escape_count += 1;
+                if let Some(kind) = EscapeKind::of(seq) {
+                    stats.by_kind[kind as usize] += 1;
+                }
+            }
+        }
+    }
+    stats.escape_bytes = s.len() - stats.text_bytes;
+    stats
+}
+
+/// The length in bytes of `s` once stripped, without building the stripped text.
+///
+/// Always `strip_ansi(s).len()`: a sequence cut off at the end counts for nothing, as it's
+/// dropped.
+pub fn strip_len(s: &str) -> usize {
+    s.non_esc().map(str::len).sum()
+}
+
+/// The mode the state machine is in after the whole of `s`.
+///
+/// Anything but `Mode::Normal` means `s` ends inside a sequence, so a streaming caller
+/// needs more input before the end of `s` can be stripped.
+pub fn mode_after(s: &str) -> Mode {
+    let mut stripper = s.non_esc();
+    while stripper.next_span().is_some() {}
+    stripper.unterminated.map_or(Mode::Normal, |(_, mode)| mode)
+}
+
+/// The byte offset in `s` of the `n`th visible char (counting from 0), or `None` if there
+/// are no more than `n` of them.
+///
+/// Maps a cursor position in the stripped text back to the raw text.
+pub fn visible_char_offset(s: &str, n: usize) -> Option<usize> {
+    let base = s.as_ptr() as usize;
+    s.non_esc()
+        .flat_map(|run| {
+            let start = run.as_ptr() as usize - base;
+            run.char_indices().map(move |(i, _)| start + i)
+        })
+        .nth(n)
+}
+
+/// Call `f` on each run of visible text in `s`, as `s.non_esc().for_each(f)` does.
+pub fn for_each_run<F: FnMut(&str)>(s: &str, f: F) {
+    s.non_esc().for_each(f)
+}
+
+/// Strip `s`, also returning how many escape sequences were removed.
+#[cfg(feature = "alloc")]
+pub fn strip_counted(s: &str) -> (String, usize) {
+    let mut stripper = s.non_esc();
+    let plain = stripper.by_ref().collect();
+    (plain, stripper.escape_count())
+}
+
+/// Strip `buf` in place, keeping its allocation.
+///
+/// One forward pass runs the state machine over the chars and keeps just those it calls
+/// `Keep`, shifting them down over what's removed, so `buf` stays valid UTF-8 throughout.
+#[cfg(feature = "alloc")]
+pub fn strip_in_place(buf: &mut String) {
+    let mut mode = Mode::Normal;
+    buf.retain(|c| {
+        let action;
+        (mode, action) = transition(mode, c);
+        action == Action::Keep
+    });
+}
+
+/// Append the bytes of `src` that aren't part of an escape sequence to `out`.
+///
+/// `src` needn't be UTF-8 and nothing is validated, so this reuses `out`'s capacity
+/// without any other allocation.
+#[cfg(feature = "alloc")]
+pub fn strip_bytes_into(src: &[u8], out: &mut Vec<u8>) {
+    for run in src.non_esc_bytes() {
+        out.extend_from_slice(run);
+    }
+}
+
+/// Strip `input` as bytes, then decode what's left as UTF-8, replacing anything invalid with
+/// U+FFFD as `String::from_utf8_lossy` does.
+///
+/// Sequences are found in the raw bytes, so invalid UTF-8 next to or inside one doesn't stop
+/// it being removed, and only the visible text is ever replaced.
+#[cfg(feature = "alloc")]
+pub fn non_esc_lossy(input: &[u8]) -> String {
+    let mut out = Vec::with_capacity(input.len());
+    strip_bytes_into(input, &mut out);
+    match String::from_utf8(out) {
+        Ok(text) => text,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+/// Strip `src`, borrowing it if there's nothing to strip and using `buf` if there is.
+///
+/// `buf` is cleared and written only when something was stripped, so one buffer can be
+/// reused across many mostly clean inputs.
+#[cfg(feature = "alloc")]
+pub fn strip_smart<'a>(src: &'a str, buf: &'a mut String) -> &'a str {
+    let mut stripper = src.non_esc();
+    let first = match stripper.next() {
+        Some(first) if first.len() < src.len() => first,
+        _ if stripper.escape_count() == 0 && stripper.unterminated.is_none() => return src,
+        _ => "",
+    };
+    buf.clear();
+    buf.push_str(first);
+    buf.extend(stripper);
+    buf
+}
+
+/// Strip `s` into an `Arc<str>`, to share between threads without copying it again.
+///
+/// Input with nothing to strip goes straight into the `Arc`, without the `String` that
+/// `Arc::from(strip_ansi(s))` would build first.
+#[cfg(feature = "alloc")]
+pub fn strip_shared(s: &str) -> Arc<str> {
+    let mut buf = String::new();
+    Arc::from(strip_smart(s, &mut buf))
+}
+
+/// Strip `s` and split the visible text at the first `delim`.
+#[cfg(feature = "alloc")]
+pub fn strip_split_once(s: &str, delim: char) -> Option<(String, String)> {
+    let plain = strip_ansi(s);
+    plain
+        .split_once(delim)
+        .map(|(head, tail)| (head.to_string(), tail.to_string()))
+}
+
+/// Strip `s` and split the visible text at the first tab, for TSV-like coloured output.
+///
+/// Without a tab the whole visible text is the first field and there's no rest.
+#[cfg(feature = "alloc")]
+pub fn strip_first_field(s: &str) -> (String, Option<String>) {
+    let mut plain = strip_ansi(s);
+    match plain.find('\t') {
+        Some(tab) => {
+            let rest = plain[tab + 1..].to_string();
+            plain.truncate(tab);
+            (plain, Some(rest))
+        }
+        None => (plain, None),
+    }
+}
+
+/// Strip `s`, collapse each run of whitespace to a single space and trim both ends.
+///
+/// Whitespace is as `char::is_whitespace` has it, so newlines and tabs collapse too. Handy
+/// for comparing coloured output without caring how it was laid out.
+#[cfg(feature = "alloc")]
+pub fn strip_collapse_ws(s: &str) -> String {
+    strip_ansi(s)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Strip `s` and end what's left with exactly one `\n`, for tools that want newline-terminated
+/// files.
+///
+/// A missing newline is added and a run of them at the end is cut to one. Input that strips
+/// to nothing stays empty rather than becoming a lone newline.
+#[cfg(feature = "alloc")]
+pub fn strip_ensure_newline(s: &str) -> String {
+    let mut plain = strip_ansi(s);
+    if !plain.is_empty() {
+        plain.truncate(plain.trim_end_matches('\n').len());
+        plain.push('\n');
+    }
+    plain
+}
+
+/// Strip `s`, then resolve backspace and carriage-return overstrike as a terminal would show
+/// it, for man pages and progress output saved as plain text.
+///
+/// A backspace removes the char before it, and does nothing at the start of a line. A `\r`
+/// not followed by `\n` clears the line so far, so only its last overwrite is left; `\r\n`
+/// is kept as a line ending.
+#[cfg(feature = "alloc")]
+pub fn render(s: &str) -> String {
+    let plain = strip_ansi(s);
+    let mut out = String::with_capacity(plain.len());
+    let mut line_start = 0;
+    let mut chars = plain.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x08' => {
+                if out.len() > line_start {
+                    out.pop();
+                }
+            }
+            '\r' if chars.peek() != Some(&'\n') => out.truncate(line_start),
+            '\n' => {
+                out.push(c);
+                line_start = out.len();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Strip escape sequences and every control character except `\n` and `\t`.
+///
+/// Everything `char::is_control` reports is dropped, which covers C0 (including `\r`),
+/// DEL and C1; only newline and tab survive.
+#[cfg(feature = "alloc")]
+pub fn strip_retaining_newlines_only(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for span in s.non_esc() {
+        for run in span.split(|c: char| c.is_control() && c != '\n' && c != '\t') {
+            out.push_str(run);
+        }
+    }
+    out
+}
+
+/// The distinct control characters left in `s` once escapes are stripped, in order of first
+/// appearance.
+///
+/// Uses the same `char::is_control` test as `strip_retaining_newlines_only`, so an empty
+/// result means that function would have nothing more to drop than `\n` and `\t`.
+#[cfg(feature = "alloc")]
+pub fn remaining_controls(s: &str) -> Vec<char> {
+    let mut found = Vec::new();
+    for c in s.non_esc().flat_map(str::chars) {
+        if c.is_control() && !found.contains(&c) {
+            found.push(c);
+        }
+    }
+    found
+}
+
+/// Strip `s` and escape `<`, `>` and `&` in what's left, ready to embed in HTML text.
+///
+/// Quotes aren't escaped, so the result isn't safe inside an attribute value.
+#[cfg(feature = "alloc")]
+pub fn strip_html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.non_esc().flat_map(str::chars) {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Copy `s` with `f`'s replacement in place of each escape sequence, rather than nothing.
+///
+/// `f` gets each sequence whole, as `segments` yields it, so it can decide by kind; one cut
+/// off by the end of `s` is passed too. The text between them is copied unchanged.
+#[cfg(feature = "alloc")]
+pub fn replace_esc<'a, F, R>(s: &'a str, mut f: F) -> String
+where
+    F: FnMut(&'a str) -> R,
+    R: AsRef<str>,
+{
+    let mut out = String::with_capacity(s.len());
+    for segment in s.segments() {
+        match segment {
+            Segment::Text(text) => out.push_str(text),
+            Segment::Escape(seq) => out.push_str(f(seq).as_ref()),
+        }
+    }
+    out
+}
+
+/// Strip `s`, also returning whether each visible char was drawn in a non-default style.
+///
+/// The mask has one entry per char of the stripped text. Only SGR sequences affect it, and
+/// a char is styled when the `Sgr` that `styled_segments` would give it isn't the default.
+#[cfg(feature = "alloc")]
+pub fn strip_with_style_mask(s: &str) -> (String, Vec<bool>) {
+    let mut out = String::with_capacity(s.len());
+    let mut mask = Vec::new();
+    for (text, style) in styled_segments(s) {
+        out.push_str(text);
+        mask.extend(text.chars().map(|_| style != Sgr::default()));
+    }
+    (out, mask)
+}
+
+/// Strip `s`, also returning the byte offset into the stripped text where each line starts.
+///
+/// Lines are counted as `str::lines` counts them, so a trailing newline doesn't start
+/// another line and empty input has no lines.
+#[cfg(feature = "alloc")]
+pub fn strip_with_line_starts(s: &str) -> (String, Vec<usize>) {
+    let mut out = String::with_capacity(s.len());
+    let mut starts = vec![0];
+    for span in s.non_esc() {
+        starts.extend(span.match_indices('\n').map(|(i, _)| out.len() + i + 1));
+        out.push_str(span);
+    }
+    if starts.last() == Some(&out.len()) {
+        starts.pop();
+    }
+    (out, starts)
+}
+
+/// Strip `s` as one stream and split the visible text into lines, without terminators.
+///
+/// Unlike calling `non_esc` on each of `s.lines()`, a sequence that spans a newline is
+/// removed whole: the lines are pushed through a `StatefulStripper` in turn, and only the
+/// newlines left in its output end a line.
+#[cfg(feature = "alloc")]
+pub fn strip_lines_streaming(s: &str) -> Vec<String> {
+    let mut stripper = StatefulStripper::new();
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for chunk in s.split_inclusive('\n') {
+        for piece in stripper.push(chunk).split_inclusive('\n') {
+            match piece.strip_suffix('\n') {
+                Some(line) => {
+                    current.push_str(line.strip_suffix('\r').unwrap_or(line));
+                    lines.push(core::mem::take(&mut current));
+                }
+                None => current.push_str(piece),
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Read the file at `path` and return its lines with escapes stripped and terminators removed.
+///
+/// The file is stripped as one stream before it's split, so a sequence that spans lines is
+/// still removed whole.
+#[cfg(feature = "std")]
+pub fn strip_file_lines<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    let plain = strip_ansi(&contents);
+    Ok(plain.lines().map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visibly_empty() {
+        assert!(is_visibly_empty(""));
+        assert!(is_visibly_empty("\x1b[0m"));
+        assert!(is_visibly_empty("\x1b[0m\x1b]0;title\x07\x1b["));
+        assert!(!is_visibly_empty("\x1b[0mx"));
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(0, escape_count("plain"));
+        assert_eq!(2, escape_count("a\x1b[0mb\x1b[1m"));
+        assert_eq!(1, escape_count("\x1b[0m\x1b["));
+    }
+
+    #[test]
+    fn len() {
+        assert_eq!(0, strip_len(""));
+        assert_eq!(5, strip_len("plain"));
+        assert_eq!(2, strip_len("a\x1b[0mb"));
+        assert_eq!(2, strip_len("é\x1b]0;unterminated"));
+        assert_eq!(1, strip_len("a\x1b"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn collapse_ws() {
+        assert_eq!(
+            "foo bar",
+            strip_collapse_ws("  \x1b[31mfoo\x1b[0m\t\tbar  ")
+        );
+        assert_eq!(
+            "a b c",
+            strip_collapse_ws("a\x1b[0m \x1b[1m\n b\r\n\u{a0}c\n")
+        );
+        assert_eq!("ab", strip_collapse_ws("a\x1b[0mb"));
+        assert_eq!("", strip_collapse_ws(" \x1b[0m\n\t"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn ensure_newline() {
+        assert_eq!("a\nb\n", strip_ensure_newline("a\n\x1b[1mb\x1b[0m"));
+        assert_eq!("a\n", strip_ensure_newline("a\n\n\x1b[0m\n\n"));
+        assert_eq!("a\n", strip_ensure_newline("a\n"));
+        assert_eq!("", strip_ensure_newline(""));
+        assert_eq!("", strip_ensure_newline("\x1b[0m"));
+        assert_eq!("\n", strip_ensure_newline("\n\n"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn render_overstrike() {
+        assert_eq!("b", render("a\x08b"));
+        // Man page bold and underline.
+        assert_eq!("NAME _x", render("N\x08NA\x08AM\x08ME\x08E _\x08_x"));
+        assert_eq!("a\nb", render("a\n\x08b"));
+        assert_eq!("", render("\x08\x08"));
+        assert_eq!(
+            "done 100%\nnext\r\n",
+            render("\x1b[1m 10%\x1b[0m\r 50%\r\x1b[32mdone 100%\x1b[0m\nnext\r\n")
+        );
+        assert_eq!("a\nb\n", render("a\n\x1b[2Kb\rb\n"));
+        assert_eq!("", render("progress\r"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn retaining_newlines_only() {
+        assert_eq!(
+            "a\tb\nc\n",
+            strip_retaining_newlines_only("\x1b[1ma\x1b[0m\tb\r\nc\x07\x08\x7f\u{85}\r\n")
+        );
+        assert_eq!("plain", strip_retaining_newlines_only("plain"));
+        assert_eq!("", strip_retaining_newlines_only("\r\x00\x1b[2J"));
+        assert_eq!("ab", strip_retaining_newlines_only("a\0\x1b[0m\0b"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn controls_remaining() {
+        assert_eq!(
+            vec!['\t', '\x07'],
+            remaining_controls("\x1b[1ma\tb\x1b[0m\x07\x1b]0;t\x07\tc\x07")
+        );
+        assert_eq!(
+            Vec::<char>::new(),
+            remaining_controls("\x1b[31mplain\x1b[0m")
+        );
+        assert_eq!(vec!['\n', '\r'], remaining_controls("a\n\x1b[0mb\r\n"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn html_escape() {
+        assert_eq!(
+            "&lt;tag&gt; &amp; more",
+            strip_html_escape("\x1b[31m<tag> & more\x1b[0m")
+        );
+        assert_eq!("a&amp;b", strip_html_escape("\x1b]0;<t>&\x07a&b"));
+        assert_eq!("\"plain\"", strip_html_escape("\"plain\""));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn replace() {
+        assert_eq!("a.b", replace_esc("a\x1b[0mb", |_| "."));
+        assert_eq!("..x.", replace_esc("\x1b[1m\x1b]0;t\x07x\x1b[", |_| "."));
+        let sample = "\x1b[31mred\x1b[0m \x1b]0;title\x07done";
+        let marked = replace_esc(sample, |seq| match seq.strip_prefix("\x1b[") {
+            Some(csi) => format!("⟨CSI {csi}⟩"),
+            None => "⟨?⟩".to_string(),
+        });
+        assert_eq!("⟨CSI 31m⟩red⟨CSI 0m⟩ ⟨?⟩done", marked);
+        assert_eq!(sample, replace_esc(sample, |seq| seq));
+        assert_eq!(
+            "plain",
+            replace_esc("plain", |_| -> &str { unreachable!() })
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn style_mask() {
+        let (plain, mask) = strip_with_style_mask("a\x1b[1mbé\x1b[0mc\x1b[2Jd");
+        assert_eq!("abécd", plain);
+        assert_eq!(vec![false, true, true, false, false], mask);
+        assert_eq!(plain.chars().count(), mask.len());
+        let reversed = strip_with_style_mask("\x1b[7ma\x1b[27mb\x1b[2mc");
+        assert_eq!(("abc".to_string(), vec![true, false, true]), reversed);
+        // It agrees with `styled_segments`, which ignores a colour with no arguments.
+        let agrees = |s: &str| {
+            let styled: Vec<bool> = styled_segments(s)
+                .flat_map(|(text, sgr)| text.chars().map(move |_| sgr != Sgr::default()))
+                .collect();
+            assert_eq!(styled, strip_with_style_mask(s).1, "{s:?}");
+        };
+        agrees("\x1b[38;5ma\x1b[9mb\x1b[0m\x1b[53mc\x1b[5;1md");
+        let c1 = strip_with_style_mask("a\u{9b}1mb\u{9b}0mc");
+        assert_eq!(("abc".to_string(), vec![false, true, false]), c1);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn style_mask_partial_clears() {
+        let (plain, mask) = strip_with_style_mask("\x1b[1;31ma\x1b[22mb\x1b[39mc");
+        assert_eq!("abc", plain);
+        assert_eq!(vec![true, true, false], mask);
+        assert_eq!(
+            (String::new(), vec![]),
+            strip_with_style_mask("\x1b[1m\x1b[0m")
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn line_starts() {
+        let (plain, starts) =
+            strip_with_line_starts("\x1b[31mred\x1b[0m\n\x1b[1mbo\x1b[0mld\n\nlast\x1b[0m");
+        assert_eq!("red\nbold\n\nlast", plain);
+        assert_eq!(vec![0, 4, 9, 10], starts);
+        for (&start, line) in starts.iter().zip(plain.lines()) {
+            assert!(plain[start..].starts_with(line));
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn line_starts_trailing_newline() {
+        assert_eq!(
+            ("a\nb\n".to_string(), vec![0, 2]),
+            strip_with_line_starts("a\n\x1b[0mb\n\x1b[0m")
+        );
+        assert_eq!((String::new(), vec![]), strip_with_line_starts("\x1b[0m"));
+        assert_eq!(("\n".to_string(), vec![0]), strip_with_line_starts("\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn file_lines() {
+        let path = std::env::temp_dir().join(format!("ansi-strip-{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            "\x1b[31mred\x1b[0m\r\n\x1b[1mbold\x1b[0m\n\nlast\x1b]0;t\nt\x07",
+        )
+        .unwrap();
+        let lines = strip_file_lines(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(vec!["red", "bold", "", "last"], lines.unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn file_lines_missing() {
+        assert!(strip_file_lines("/nonexistent/ansi-strip").is_err());
+    }
+
+    #[test]
+    fn modes_after() {
+        assert_eq!(Mode::Normal, mode_after(""));
+        assert_eq!(Mode::Normal, mode_after("abc"));
+        assert_eq!(Mode::Normal, mode_after("a\x1b[0m"));
+        assert_eq!(Mode::InEsc, mode_after("a\x1b"));
+        assert_eq!(Mode::InCsi, mode_after("a\x1b["));
+        assert_eq!(Mode::InOsc, mode_after("a\x1b]0;x"));
+        assert_eq!(Mode::OscMaybeSt, mode_after("a\x1b]0;x\x1b"));
+        assert_eq!(Mode::AwaitSt, mode_after("\x1bPq"));
+        assert_eq!(Mode::InNf, mode_after("\x1b("));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn counted() {
+        assert_eq!(("ab".to_string(), 2), strip_counted("a\x1b[0mb\x1b[1m"));
+        assert_eq!(("plain".to_string(), 0), strip_counted("plain"));
+        assert_eq!(("a".to_string(), 0), strip_counted("a\x1b[1"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn in_place() {
+        let mut buf = String::with_capacity(64);
+        buf.push_str("\x1b[1;31merror\x1b[0m: \x1b]0;t\x07é\x1b[");
+        let (ptr, capacity) = (buf.as_ptr(), buf.capacity());
+        strip_in_place(&mut buf);
+        assert_eq!("error: é", buf);
+        assert_eq!((ptr, capacity), (buf.as_ptr(), buf.capacity()));
+
+        let mut clean = String::from("plain\ttext\n");
+        strip_in_place(&mut clean);
+        assert_eq!("plain\ttext\n", clean);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn bytes_into_reused_buffer() {
+        let inputs: [&[u8]; 4] = [
+            b"\x1b[1mbold\x1b[0m",
+            b"\xff\x1b]0;t\x07\xfe",
+            b"",
+            b"plain\x1b[",
+        ];
+        let mut out = Vec::with_capacity(64);
+        for input in inputs {
+            out.clear();
+            strip_bytes_into(input, &mut out);
+            let standalone: Vec<u8> = input.non_esc_bytes().flatten().copied().collect();
+            assert_eq!(standalone, out);
+            assert_eq!(64, out.capacity());
+        }
+        assert_eq!(b"plain", &out[..]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn bytes_into_appends() {
+        let mut out = b"> ".to_vec();
+        strip_bytes_into(b"\x1b[31ma\x1b[0m", &mut out);
+        assert_eq!(b"> a", &out[..]);
+    }
+
+    #[test]
+    fn counts() {
+        let s = "\x1b[1mbold\x1b[0m \x1b]0;title\x07\x1b[2Jx\x1b[3\ny\u{9b}K\x1b[";
+        let stats = count_escapes(s);
+        assert_eq!(
+            StripStats {
+                text_bytes: 8,
+                escape_bytes: 30,
+                escape_count: 5,
+                ..stats
+            },
+            stats
+        );
+        assert_eq!(2, stats.sequences_by_kind(EscapeKind::Sgr));
+        assert_eq!(1, stats.sequences_by_kind(EscapeKind::Osc));
+        assert_eq!(2, stats.sequences_by_kind(EscapeKind::Csi));
+        assert_eq!(0, stats.sequences_by_kind(EscapeKind::Dcs));
+        assert_eq!(StripStats::default(), count_escapes(""));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn lossy() {
+        assert_eq!("a\u{fffd}b", non_esc_lossy(b"\x1b[31ma\xffb\x1b[0m"));
+        assert_eq!("\u{fffd}x", non_esc_lossy(b"\x1b]0;\xfe\xff\x07\xc3x"));
+        // Invalid UTF-8 at either end of an escape doesn't hide it.
+        assert_eq!("\u{fffd}\u{fffd}", non_esc_lossy(b"\xff\x1b[1m\xfe\x1b[0m"));
+        assert_eq!("é", non_esc_lossy("\x1b[1mé".as_bytes()));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn smart_borrows_when_clean() {
+        let mut buf = String::from("untouched");
+        let src = "plain text";
+        let out = strip_smart(src, &mut buf);
+        assert_eq!(src, out);
+        assert_eq!(src.as_ptr(), out.as_ptr());
+        assert_eq!("untouched", buf);
+        assert_eq!("", strip_smart("", &mut buf));
+        assert_eq!("untouched", buf);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn smart_writes_when_dirty() {
+        let mut buf = String::from("old");
+        assert_eq!("ab", strip_smart("a\x1b[1mb", &mut buf));
+        assert_eq!("ab", buf);
+        for (src, expected) in [("\x1b[0m", ""), ("a\x1b]0;t", "a"), ("\x1b[1mab", "ab")] {
+            assert_eq!(expected, strip_smart(src, &mut buf));
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn shared() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let shared = strip_shared("\x1b[1;31merror\x1b[0m: broken");
+        assert_send_sync(&shared);
+        assert_eq!("error: broken", &*shared);
+        assert_eq!("clean", &*strip_shared("clean"));
+        let handle = std::thread::spawn({
+            let shared = Arc::clone(&shared);
+            move || shared.len()
+        });
+        assert_eq!(13, handle.join().unwrap());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn split_once_key_value() {
+        assert_eq!(
+            Some(("key".to_string(), " value".to_string())),
+            strip_split_once("\x1b[1mkey\x1b[0m: value", ':')
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn split_once_ignores_delim_in_escape() {
+        assert_eq!(
+            Some(("k".to_string(), "v".to_string())),
+            strip_split_once("\x1b]0;a:b\x07k:v", ':')
+        );
+        assert_eq!(None, strip_split_once("\x1b]0;a:b\x07kv", ':'));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn first_field() {
+        assert_eq!(
+            ("name".to_string(), Some("value".to_string())),
+            strip_first_field("\x1b[1mname\x1b[0m\tvalue")
+        );
+        assert_eq!(
+            ("a".to_string(), Some("b\tc".to_string())),
+            strip_first_field("a\t\x1b[31mb\tc")
+        );
+        assert_eq!(
+            ("ab".to_string(), None),
+            strip_first_field("\x1b]0;x\ty\x07ab")
+        );
+        assert_eq!(
+            (String::new(), Some(String::new())),
+            strip_first_field("\t")
+        );
+    }
+
+    #[test]
+    fn preserves_width() {
+        assert!(strip_preserves_width(
+            "\x1b[1;31mred\x1b[0m \x1b]0;title\x07done\r\n"
+        ));
+        assert!(strip_preserves_width("plain"));
+        assert!(!strip_preserves_width("\x1b[1mname\x1b[0m\tvalue"));
+        assert!(!strip_preserves_width("done\t"));
+        assert!(!strip_preserves_width("a_\x08b"));
+        assert!(!strip_preserves_width("50%\r100%"));
+        // Only what's shown counts, and an escape between CR and LF doesn't split them.
+        assert!(strip_preserves_width("a\x1b]0;\tt\x07b\r\x1b[0m\n"));
+        assert!(!strip_preserves_width("name\x1b[Ivalue"));
+        assert!(!strip_preserves_width("a\x1b[5Cb"));
+        assert!(!strip_preserves_width("a\u{9b}10Gb"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn lines_streaming() {
+        let straddling = "a\x1b]0;two\nline title\x07b\nc\x1b[1m\r\n\x1b[0m\n";
+        assert_eq!(vec!["ab", "c", ""], strip_lines_streaming(straddling));
+        // Stripping line by line can't see the sequence across the newline.
+        let per_line: Vec<String> = straddling.lines().map(strip_ansi).collect();
+        assert_eq!(vec!["a", "line title\x07b", "c", ""], per_line);
+        assert_eq!(Vec::<String>::new(), strip_lines_streaming(""));
+        assert_eq!(vec!["x"], strip_lines_streaming("x\x1b[0"));
+    }
+
+    #[test]
+    fn char_offsets() {
+        assert_eq!(Some(0), visible_char_offset("a\x1b[0mb", 0));
+        assert_eq!(Some(5), visible_char_offset("a\x1b[0mb", 1));
+        assert_eq!(None, visible_char_offset("a\x1b[0mb", 2));
+        assert_eq!(Some(11), visible_char_offset("\x1b[1m漢\x1b[0mé", 1));
+        assert_eq!(None, visible_char_offset("\x1b[1m", 0));
+    }
+}
@@ -0,0 +1,294 @@
+//! Stripping byte streams as they pass through `std::io` readers and writers.
+
+use std::io::{self, Read, Write};
+
+use crate::StripState;
+
+/// How much to read from the inner reader at a time.
+const CHUNK: usize = 8 * 1024;
+
+/// A reader that strips escape sequences from everything read through it.
+///
+/// A sequence split across reads of the inner reader is still removed whole. One still
+/// incomplete at EOF is dropped, as the iterators drop it at the end of their input.
+#[derive(Debug)]
+pub struct StripReader<R> {
+    inner: R,
+    state: StripState,
+    out: Vec<u8>,
+    out_pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> StripReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            state: StripState::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Unwrap the inner reader, losing anything read from it but not yet returned.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for StripReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Keep reading until some visible bytes turn up, so Ok(0) only ever means EOF.
+        while self.out_pos == self.out.len() {
+            if self.eof || buf.is_empty() {
+                return Ok(0);
+            }
+            let mut chunk = [0; CHUNK];
+            let n = match self.inner.read(&mut chunk) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.out.clear();
+            self.out_pos = 0;
+            if n == 0 {
+                self.eof = true;
+                self.state
+                    .finish(&mut |run| self.out.extend_from_slice(run));
+            } else {
+                self.state
+                    .feed(&chunk[..n], &mut |run| self.out.extend_from_slice(run));
+            }
+        }
+        let n = buf.len().min(self.out.len() - self.out_pos);
+        buf[..n].copy_from_slice(&self.out[self.out_pos..self.out_pos + n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
+
+/// A writer that strips escape sequences from everything written through it.
+///
+/// A sequence split across writes is still removed whole. Visible bytes go straight to the
+/// inner writer; only an incomplete sequence is held, and it's lost if the writer is dropped.
+///
+/// If the inner writer fails once the input has been taken in, the write still succeeds and
+/// the visible bytes it couldn't pass on are tried again, ahead of anything else, by the next
+/// `write` or `flush`, which return the error if it happens again.
+#[derive(Debug)]
+pub struct StripWriter<W: Write> {
+    inner: W,
+    state: StripState,
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<W: Write> StripWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            state: StripState::new(),
+            out: Vec::new(),
+            out_pos: 0,
+        }
+    }
+
+    /// Unwrap the inner writer, dropping any incomplete sequence and any visible bytes it
+    /// hasn't yet taken.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Pass on whatever visible bytes the inner writer hasn't taken yet.
+    fn drain(&mut self) -> io::Result<()> {
+        while self.out_pos < self.out.len() {
+            match self.inner.write(&self.out[self.out_pos..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => self.out_pos += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.out.clear();
+        self.out_pos = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for StripWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Until the earlier output is through, none of `buf` is taken, so a retry is safe.
+        self.drain()?;
+        self.state
+            .feed(buf, &mut |run| self.out.extend_from_slice(run));
+        // `buf` is in the state machine now and mustn't be fed again, so it counts as
+        // written; if this fails, the next write or flush tries again.
+        let _ = self.drain();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strip_ansi;
+
+    /// Hands out its bytes one at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&b, rest)) if !buf.is_empty() => {
+                    buf[0] = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    const SAMPLE: &str =
+        "a\x1b[1;31mred\x1b[0m\n\x1b]8;;http://x\x1b\\link\n\x1b]8;;\x1b\\\x1bPq\n#0\x1b\\b\n";
+
+    fn read_all(reader: impl Read) -> String {
+        let mut out = String::new();
+        StripReader::new(reader).read_to_string(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn one_byte_at_a_time() {
+        assert_eq!(strip_ansi(SAMPLE), read_all(Trickle(SAMPLE.as_bytes())));
+    }
+
+    #[test]
+    fn whole_reader() {
+        assert_eq!(strip_ansi(SAMPLE), read_all(SAMPLE.as_bytes()));
+        assert_eq!("", read_all(&b""[..]));
+        assert_eq!("", read_all(&b"\x1b[0m"[..]));
+    }
+
+    #[test]
+    fn small_reads() {
+        let mut reader = StripReader::new(Trickle(b"ab\x1b[1mcd"));
+        let mut buf = [0; 3];
+        let mut out = Vec::new();
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(b"abcd", &out[..]);
+    }
+
+    #[test]
+    fn incomplete_at_eof_dropped() {
+        assert_eq!("ab", read_all(Trickle(b"ab\x1b]0;unterminated")));
+        assert_eq!("ab", read_all(&b"a\x1b[0mb\x1b["[..]));
+    }
+
+    #[test]
+    fn not_utf8() {
+        let mut out = Vec::new();
+        StripReader::new(Trickle(b"\xff\x1b[1m\xfe"))
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(b"\xff\xfe", &out[..]);
+    }
+
+    #[test]
+    fn writer_chunks_split_mid_sequence() {
+        let colored = "\x1b[1;31merror\x1b[0m: \x1b]0;title\x07broken\n";
+        for split in 1..colored.len() {
+            let mut writer = StripWriter::new(Vec::new());
+            writer.write_all(&colored.as_bytes()[..split]).unwrap();
+            writer.write_all(&colored.as_bytes()[split..]).unwrap();
+            writer.flush().unwrap();
+            assert_eq!(
+                b"error: broken\n",
+                &writer.into_inner()[..],
+                "split at {split}"
+            );
+        }
+    }
+
+    #[test]
+    fn writer_formatting() {
+        let mut writer = StripWriter::new(Vec::new());
+        let bold = "\x1b[1mbold\x1b[0m";
+        writeln!(writer, "{bold} {}", 42).unwrap();
+        assert_eq!(b"bold 42\n", &writer.into_inner()[..]);
+    }
+
+    /// Fails its first `fails` writes, then collects what's written.
+    struct Flaky {
+        fails: usize,
+        kind: io::ErrorKind,
+        out: Vec<u8>,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.fails > 0 {
+                self.fails -= 1;
+                return Err(self.kind.into());
+            }
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn flaky(fails: usize, kind: io::ErrorKind) -> StripWriter<Flaky> {
+        StripWriter::new(Flaky {
+            fails,
+            kind,
+            out: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn writer_inner_fails_once() {
+        let mut writer = flaky(1, io::ErrorKind::Other);
+        assert_eq!(4, writer.write(b"a\x1b[1").unwrap());
+        writer.write_all(b"mb\x1b[0m").unwrap();
+        assert_eq!(b"ab", &writer.into_inner().out[..]);
+
+        // An `Interrupted` from the inner writer is simply tried again.
+        let mut writer = flaky(1, io::ErrorKind::Interrupted);
+        writer.write_all(b"a\x1b").unwrap();
+        writer.write_all(b"[1mb").unwrap();
+        assert_eq!(b"ab", &writer.into_inner().out[..]);
+    }
+
+    #[test]
+    fn writer_error_reported_on_retry() {
+        let mut writer = flaky(2, io::ErrorKind::Other);
+        assert_eq!(4, writer.write(b"ab\x1b[").unwrap());
+        assert!(writer.flush().is_err());
+        // The held-back "ab" goes out first, then this write's "c".
+        assert!(writer.write(b"1mc").is_ok());
+        writer.flush().unwrap();
+        assert_eq!(b"abc", &writer.into_inner().out[..]);
+    }
+
+    #[test]
+    fn writer_drops_incomplete_sequence() {
+        let mut writer = StripWriter::new(Vec::new());
+        writer.write_all(b"ab\x1b]0;unterm").unwrap();
+        assert_eq!(b"ab", &writer.into_inner()[..]);
+    }
+}
@@ -0,0 +1,409 @@
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::sgr::is_sgr;
+use crate::{APC, CSI, DCS, ESC, OSC, PM, SOC, ST};
+
+const ESC_BYTE: u8 = ESC as u8;
+
+/// The char after `ESC` that introduces each kind of string or control sequence, and the C1
+/// control that does the same on its own.
+const INTRODUCERS: [(char, u8); 6] = [
+    (CSI, 0x9b),
+    (OSC, 0x9d),
+    (DCS, 0x90),
+    (SOC, 0x98),
+    (PM, 0x9e),
+    (APC, 0x9f),
+];
+
+/// The broad category of an escape sequence, as decided by its introducer and, for a CSI,
+/// its final byte.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EscapeKind {
+    /// Control Sequence Introducer: `ESC [` or U+009B, other than an SGR or window op.
+    Csi,
+    /// Select Graphic Rendition, the CSI ending in `m` that sets colour and style.
+    Sgr,
+    /// A window manipulation CSI, ending in `t`, e.g. `ESC [ 8 ; 24 ; 80 t` to resize or
+    /// `ESC [ 22 ; 0 t` to save the title.
+    WindowOp,
+    /// Operating System Command: `ESC ]` or U+009D
+    Osc,
+    /// Device Control String: `ESC P` or U+0090
+    Dcs,
+    /// Start Of String: `ESC X` or U+0098
+    Sos,
+    /// Privacy Message: `ESC ^` or U+009E
+    Pm,
+    /// Application Program Command: `ESC _` or U+009F
+    Apc,
+    /// Any other escape, e.g. the two-byte `ESC 7`.
+    Other,
+}
+
+impl EscapeKind {
+    /// Classify a raw escape sequence, or `None` if it doesn't start with an escape.
+    ///
+    /// The introducer may be `ESC` and the char after it, or the matching C1 control such as
+    /// U+009B for CSI.
+    pub fn of(seq: &str) -> Option<Self> {
+        Self::of_bytes(seq.as_bytes(), ESC_BYTE)
+    }
+
+    /// `of`, with `esc` standing for ESC as `AnsiStripperBuilder::esc_byte` sets it.
+    pub(crate) fn of_bytes(seq: &[u8], esc: u8) -> Option<Self> {
+        let Some(second) = INTRODUCERS
+            .into_iter()
+            .find(|&(second, c1)| after_introducer(seq, esc, second as u8, c1).is_some())
+            .map(|(second, _)| second)
+        else {
+            return matches!(seq, [e, _, ..] if *e == esc).then_some(Self::Other);
+        };
+        Some(match second {
+            CSI if is_sgr(seq, esc) => Self::Sgr,
+            CSI if is_window_op(seq, esc) => Self::WindowOp,
+            CSI => Self::Csi,
+            OSC => Self::Osc,
+            DCS => Self::Dcs,
+            SOC => Self::Sos,
+            PM => Self::Pm,
+            _ => Self::Apc,
+        })
+    }
+
+    /// This kind's bit in a set of kinds.
+    pub(crate) fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// Is this a window manipulation CSI: parameters, no intermediates, and a final `t`?
+pub(crate) fn is_window_op(seq: &[u8], esc: u8) -> bool {
+    match after_introducer(seq, esc, b'[', 0x9b).and_then(|s| s.strip_suffix(b"t")) {
+        Some(params) => params.iter().all(|b| (b'0'..=b'?').contains(b)),
+        None => false,
+    }
+}
+
+/// Whether a stream introduces its controls with `ESC` (7-bit) or with C1 characters (8-bit).
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ControlMode {
+    /// `ESC [`, `ESC ]` and friends.
+    #[default]
+    SevenBit,
+    /// The single-character C1 introducers, e.g. U+009B for CSI and U+009D for OSC.
+    EightBit,
+}
+
+/// Which chars can end a CSI, after its parameter and intermediate bytes.
+///
+/// A char that can't is malformed: the CSI so far is dropped and that char is kept as text.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum CsiFinalRange {
+    /// `@` to `~`, every final byte ECMA-48 allows, including the private-use `p` to `~`.
+    #[default]
+    Standard,
+    /// `@` to `o`, only ECMA-48's standardised finals, so a private-use final such as the `q`
+    /// of `ESC [ 2 SP q` is left as text.
+    Strict,
+    /// `@` and above, except controls such as DEL, so odd finals beyond `~`, even non-ASCII
+    /// ones, end the sequence too.
+    Permissive,
+    /// Whatever the function accepts. It's only asked about chars that aren't parameter or
+    /// intermediate bytes, ESC or a C1 introducer.
+    Custom(fn(char) -> bool),
+}
+
+impl CsiFinalRange {
+    /// Can `c` end a CSI?
+    pub fn accepts(self, c: char) -> bool {
+        match self {
+            Self::Standard => ('@'..='~').contains(&c),
+            Self::Strict => ('@'..='o').contains(&c),
+            Self::Permissive => c >= '@' && !c.is_control(),
+            Self::Custom(f) => f(c),
+        }
+    }
+}
+
+/// Guess the control mode of a stream from a sample of it.
+///
+/// A single C1 CSI (U+009B) or OSC (U+009D) is enough for `EightBit`, since 8-bit streams
+/// often carry some 7-bit sequences too. A sample with neither is `SevenBit`. Pass the
+/// result to `AnsiStripperBuilder::control_mode`.
+pub fn detect_control_mode(sample: &str) -> ControlMode {
+    if sample.contains(['\u{9b}', '\u{9d}']) {
+        ControlMode::EightBit
+    } else {
+        ControlMode::SevenBit
+    }
+}
+
+/// The rest of `seq` after its introducer, if that's `esc` and then `second`, or the C1
+/// control `c1`: encoded as UTF-8 as in a `&str`, or as its single byte as in a `&[u8]`.
+pub(crate) fn after_introducer(seq: &[u8], esc: u8, second: u8, c1: u8) -> Option<&[u8]> {
+    match seq {
+        [e, s, rest @ ..] if *e == esc && *s == second => Some(rest),
+        [0xc2, c, rest @ ..] | [c, rest @ ..] if *c == c1 => Some(rest),
+        _ => None,
+    }
+}
+
+/// `body` without the terminator of its OSC: ST, BEL or the C1 ST (U+009C), in either of the
+/// forms `after_introducer` takes.
+fn before_osc_terminator(body: &[u8]) -> Option<&[u8]> {
+    [ST.as_bytes(), b"\x07", "\u{9c}".as_bytes(), b"\x9c"]
+        .into_iter()
+        .find_map(|end| body.strip_suffix(end))
+}
+
+/// Split a complete OSC into its command number and payload, e.g. `(9, "message")`.
+///
+/// The OSC may use the C1 forms of its introducer and terminator, U+009D and U+009C.
+/// Returns `None` if `seq` isn't a terminated OSC or doesn't start with a command number.
+pub fn parse_osc(seq: &str) -> Option<(u32, &str)> {
+    let rest = after_introducer(seq.as_bytes(), ESC_BYTE, OSC as u8, 0x9d)?;
+    let start = seq.len() - rest.len();
+    let end = start + before_osc_terminator(rest)?.len();
+    // A lone 0x9c byte ends the last char rather than terminating, so isn't a boundary.
+    let body = seq.get(start..end)?.trim_end_matches(ESC);
+    let (command, payload) = body.split_once(';').unwrap_or((body, ""));
+    Some((command.parse().ok()?, payload))
+}
+
+/// Where the URI is in a complete OSC 8 hyperlink, `ESC ] 8 ; params ; URI ST`.
+///
+/// The range is empty for the closing `ESC ] 8 ; ; ST`. As for `parse_osc`, the C1 forms
+/// of the introducer and terminator are recognised too.
+pub(crate) fn hyperlink_uri(seq: &[u8]) -> Option<Range<usize>> {
+    let body = after_introducer(seq, ESC_BYTE, OSC as u8, 0x9d)?.strip_prefix(b"8;")?;
+    let prefix_len = seq.len() - body.len();
+    let body = before_osc_terminator(body)?;
+    // Errant ESCs before the terminator aren't part of the URI.
+    let end = body.iter().rposition(|&b| b != 0x1b).map_or(0, |i| i + 1);
+    let params_end = body[..end].iter().position(|&b| b == b';')?;
+    Some(prefix_len + params_end + 1..prefix_len + end)
+}
+
+/// Split an OSC 52 (clipboard) payload `Pc;Pd` into its selection and decoded data.
+///
+/// Returns `None` for a query (`Pd` of `?`) or data that isn't valid base64.
+#[cfg(feature = "alloc")]
+pub(crate) fn parse_clipboard(payload: &str) -> Option<(&str, Vec<u8>)> {
+    let (selection, data) = payload.split_once(';')?;
+    Some((selection, decode_base64(data)?))
+}
+
+/// Decode standard, optionally padded, base64.
+///
+/// A lone digit left over at the end can't make a byte, so it's invalid, as is padding that
+/// doesn't bring the length up to a multiple of four.
+#[cfg(feature = "alloc")]
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let digits = data.trim_end_matches('=').as_bytes();
+    let padding = data.len() - digits.len();
+    if digits.len() % 4 == 1 || (padding > 0 && (padding > 2 || !data.len().is_multiple_of(4))) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for &b in digits {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_an_escape() {
+        assert_eq!(None, EscapeKind::of(""));
+        assert_eq!(None, EscapeKind::of("abc"));
+        assert_eq!(None, EscapeKind::of("\x1b"));
+    }
+
+    #[test]
+    fn introducers() {
+        assert_eq!(Some(EscapeKind::Csi), EscapeKind::of("\x1b[2J"));
+        assert_eq!(Some(EscapeKind::Sgr), EscapeKind::of("\x1b[0m"));
+        assert_eq!(Some(EscapeKind::Csi), EscapeKind::of("\x1b[>4;2m"));
+        assert_eq!(Some(EscapeKind::Osc), EscapeKind::of("\x1b]0;title\x07"));
+        assert_eq!(Some(EscapeKind::Sos), EscapeKind::of("\x1bXdata\x1b\\"));
+        assert_eq!(Some(EscapeKind::Pm), EscapeKind::of("\x1b^data\x1b\\"));
+        assert_eq!(Some(EscapeKind::Apc), EscapeKind::of("\x1b_data\x1b\\"));
+        assert_eq!(Some(EscapeKind::Other), EscapeKind::of("\x1b7"));
+    }
+
+    #[test]
+    fn c1_introducers() {
+        assert_eq!(Some(EscapeKind::Sgr), EscapeKind::of("\u{9b}0m"));
+        assert_eq!(Some(EscapeKind::Csi), EscapeKind::of("\u{9b}2J"));
+        assert_eq!(Some(EscapeKind::WindowOp), EscapeKind::of("\u{9b}22;0t"));
+        assert_eq!(Some(EscapeKind::Osc), EscapeKind::of("\u{9d}0;title\u{9c}"));
+        assert_eq!(Some(EscapeKind::Dcs), EscapeKind::of("\u{90}q\u{9c}"));
+        assert_eq!(Some(EscapeKind::Sos), EscapeKind::of("\u{98}data\u{9c}"));
+        assert_eq!(Some(EscapeKind::Pm), EscapeKind::of("\u{9e}data\u{9c}"));
+        assert_eq!(Some(EscapeKind::Apc), EscapeKind::of("\u{9f}data\u{9c}"));
+        // As a single byte, the way an 8-bit byte stream carries it.
+        assert_eq!(
+            Some(EscapeKind::Sgr),
+            EscapeKind::of_bytes(b"\x9b1m", ESC_BYTE)
+        );
+        assert_eq!(None, EscapeKind::of("\u{9c}"));
+    }
+
+    #[test]
+    fn window_ops() {
+        for seq in ["\x1b[8;24;80t", "\x1b[22;0t", "\x1b[23;0t", "\x1b[t"] {
+            assert_eq!(Some(EscapeKind::WindowOp), EscapeKind::of(seq), "{seq:?}");
+        }
+        // DECSWBV has an intermediate space; the others aren't CSI.
+        assert_eq!(Some(EscapeKind::Csi), EscapeKind::of("\x1b[5 t"));
+        assert_eq!(Some(EscapeKind::Sgr), EscapeKind::of("\x1b[8;24;80m"));
+        assert_eq!(Some(EscapeKind::Osc), EscapeKind::of("\x1b]0;t\x07"));
+    }
+
+    #[test]
+    fn osc_parts() {
+        assert_eq!(Some((9, "done")), parse_osc("\x1b]9;done\x07"));
+        assert_eq!(Some((0, "a;b")), parse_osc("\x1b]0;a;b\x1b\\"));
+        assert_eq!(Some((8, "")), parse_osc("\x1b]8\x1b\x07"));
+        assert_eq!(None, parse_osc("\x1b]title\x07"));
+        assert_eq!(None, parse_osc("\x1b]0;unterminated"));
+        assert_eq!(None, parse_osc("\x1b[0m"));
+    }
+
+    #[test]
+    fn empty_osc() {
+        for seq in ["\x1b]\x07", "\x1b]\x1b\\", "\x1b];\x07"] {
+            assert_eq!(Some(EscapeKind::Osc), EscapeKind::of(seq));
+            assert_eq!(None, parse_osc(seq));
+        }
+    }
+
+    #[test]
+    fn osc_4_multiple_pairs() {
+        let seq = "\x1b]4;1;rgb:ff/00/00;2;rgb:00/ff/00\x1b\\";
+        assert_eq!(Some(EscapeKind::Osc), EscapeKind::of(seq));
+        assert_eq!(Some((4, "1;rgb:ff/00/00;2;rgb:00/ff/00")), parse_osc(seq));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn clipboard_payload() {
+        assert_eq!(
+            Some(("c", b"hello".to_vec())),
+            parse_clipboard("c;aGVsbG8=")
+        );
+        assert_eq!(Some(("p", b"hi!".to_vec())), parse_clipboard("p;aGkh"));
+        assert_eq!(Some(("c", vec![])), parse_clipboard("c;"));
+        assert_eq!(None, parse_clipboard("c;?"));
+        assert_eq!(None, parse_clipboard("aGVsbG8="));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn clipboard_payload_bad_length() {
+        assert_eq!(Some(("c", b"hell".to_vec())), parse_clipboard("c;aGVsbA"));
+        assert_eq!(None, parse_clipboard("c;aGVsb"));
+        assert_eq!(None, parse_clipboard("c;a"));
+        // Padding must make up a whole quantum: no more, no less.
+        assert_eq!(None, parse_clipboard("c;aGVsbG8=="));
+        assert_eq!(None, parse_clipboard("c;aGVsbA="));
+        assert_eq!(None, parse_clipboard("c;aGk=h"));
+        assert_eq!(None, parse_clipboard("c;aGVs==="));
+        assert_eq!(None, parse_clipboard("c;===="));
+    }
+
+    #[test]
+    fn decrqss() {
+        assert_eq!(Some(EscapeKind::Dcs), EscapeKind::of("\x1bP$qm\x1b\\"));
+        assert_eq!(Some(EscapeKind::Dcs), EscapeKind::of("\x1bP1$r0;1m\x1b\\"));
+    }
+
+    #[test]
+    fn control_mode_seven_bit() {
+        assert_eq!(
+            ControlMode::SevenBit,
+            detect_control_mode("\x1b[1mbold\x1b[0m \x1b]0;title\x07")
+        );
+        assert_eq!(ControlMode::SevenBit, detect_control_mode("plain"));
+        assert_eq!(ControlMode::SevenBit, detect_control_mode(""));
+    }
+
+    #[test]
+    fn control_mode_eight_bit() {
+        assert_eq!(
+            ControlMode::EightBit,
+            detect_control_mode("\u{9b}1mbold\u{9b}0m")
+        );
+        assert_eq!(
+            ControlMode::EightBit,
+            detect_control_mode("\u{9d}0;title\x07")
+        );
+        assert_eq!(
+            ControlMode::EightBit,
+            detect_control_mode("\x1b[1m\u{9b}0m")
+        );
+    }
+
+    #[test]
+    fn hyperlink_uris() {
+        let seq = b"\x1b]8;id=1;http://example.com\x1b\\";
+        assert_eq!(
+            Some(&b"http://example.com"[..]),
+            hyperlink_uri(seq).map(|r| &seq[r])
+        );
+        let seq = b"\x1b]8;;file:///tmp\x07";
+        assert_eq!(
+            Some(&b"file:///tmp"[..]),
+            hyperlink_uri(seq).map(|r| &seq[r])
+        );
+        assert_eq!(
+            Some(4..4),
+            hyperlink_uri(b"\x1b]8;;\x1b\\").map(|r| r.start - 1..r.end - 1)
+        );
+        assert_eq!(None, hyperlink_uri(b"\x1b]8;;http://x"));
+        assert_eq!(None, hyperlink_uri(b"\x1b]0;title\x07"));
+        assert_eq!(None, hyperlink_uri(b"\x1b]8;noparams\x07"));
+    }
+
+    #[test]
+    fn c1_osc_forms() {
+        assert_eq!(Some((2, "title")), parse_osc("\u{9d}2;title\u{9c}"));
+        assert_eq!(Some((0, "t")), parse_osc("\x1b]0;t\u{9c}"));
+        assert_eq!(Some((9, "é")), parse_osc("\u{9d}9;é\x1b\u{9c}"));
+        assert_eq!(None, parse_osc("\u{9b}0m"));
+        assert_eq!(None, parse_osc("\x1b]0;\u{71c}"));
+        for seq in ["\u{9d}8;;http://x\u{9c}", "\x1b]8;;http://x\u{9c}"] {
+            let seq = seq.as_bytes();
+            assert_eq!(
+                Some(&b"http://x"[..]),
+                hyperlink_uri(seq).map(|r| &seq[r]),
+                "{seq:?}"
+            );
+        }
+        // As one byte each, as in 8-bit byte input.
+        let seq = b"\x9d8;;http://x\x9c";
+        assert_eq!(Some(4..12), hyperlink_uri(seq));
+    }
+}
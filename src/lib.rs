@@ -1,4 +1,65 @@
-use std::str::CharIndices;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod builder;
+#[cfg(feature = "encoding_rs")]
+mod decode;
+mod helpers;
+#[cfg(feature = "std")]
+mod io;
+mod kind;
+mod machine;
+mod sgr;
+mod source;
+#[cfg(feature = "alloc")]
+mod stateful;
+#[cfg(feature = "anstyle")]
+mod unstyled;
+#[cfg(feature = "unicode-width")]
+mod width;
+
+use core::fmt;
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use alloc::{borrow::Cow, string::String};
+
+use builder::Options;
+use kind::hyperlink_uri;
+use sgr::is_sgr;
+
+pub use builder::AnsiStripperBuilder;
+#[cfg(feature = "encoding_rs")]
+pub use decode::strip_decode;
+#[cfg(feature = "std")]
+pub use helpers::strip_file_lines;
+pub use helpers::{
+    count_escapes, escape_count, for_each_run, is_visibly_empty, mode_after, strip_len,
+    strip_preserves_width, visible_char_offset, StripStats,
+};
+#[cfg(feature = "alloc")]
+pub use helpers::{
+    non_esc_lossy, remaining_controls, render, replace_esc, strip_bytes_into, strip_collapse_ws,
+    strip_counted, strip_ensure_newline, strip_first_field, strip_html_escape, strip_in_place,
+    strip_lines_streaming, strip_retaining_newlines_only, strip_shared, strip_smart,
+    strip_split_once, strip_with_line_starts, strip_with_style_mask,
+};
+#[cfg(feature = "std")]
+pub use io::{StripReader, StripWriter};
+pub use kind::{detect_control_mode, parse_osc, ControlMode, CsiFinalRange, EscapeKind};
+pub use machine::{transition, Action, Mode};
+#[cfg(feature = "alloc")]
+pub use sgr::{parse_sgr, strip_with_palette};
+pub use sgr::{styled_segments, Sgr, SgrColor, SgrParam, StyledSegments};
+pub use source::EscSource;
+#[cfg(feature = "alloc")]
+pub use stateful::{PushStats, StatefulStripper, StripState};
+#[cfg(feature = "anstyle")]
+pub use unstyled::{anstyle_segments, AnstyleSegments, Unstyled};
+#[cfg(all(feature = "alloc", feature = "unicode-width"))]
+pub use width::{strip_chunks, strip_pad_lines, wrapped_line_count};
 
 const ESC: char = '\x1b';
 // const LF: char = '\n';
@@ -10,13 +71,45 @@ const SOC: char = 'X';
 const PM: char = '^';
 const APC: char = '_';
 const BEL: char = '\x07';
+const TAB: char = '\t';
+const LINUX_PALETTE_SET: char = 'P';
+const LINUX_PALETTE_RESET: char = 'R';
 const ST_CHAR: char = '\\';
-#[allow(dead_code)]
 const ST: &str = "\x1b\\";
+const SGR_RESET: &str = "\x1b[0m";
+
+/// Tab stops are every `TAB_WIDTH` columns when padding out tabs.
+const TAB_WIDTH: usize = 8;
+const SPACES: &str = "        ";
 
 /// Trait to strip out ANSI Escape sequences.
+///
+/// Each call strips its own slice as the whole input, so a sequence split between two
+/// slices, as calling `non_esc` on each of `text.lines()` can split one, isn't recognised:
+/// the first part is dropped as unterminated and the rest passes through as text. Strip the
+/// whole text, or use `strip_lines_streaming` or a `StatefulStripper`, instead.
 pub trait NonEsc<'a> {
     fn non_esc(self) -> AnsiStripper<'a>;
+
+    /// Display the stripped text, for `write!` or `format!` without building a `String`.
+    fn non_esc_display(self) -> NonEscDisplay<'a>
+    where
+        Self: Sized,
+    {
+        NonEscDisplay(self.non_esc().src)
+    }
+}
+
+/// Displays a string slice with its escape sequences stripped.
+///
+/// Each visible run is written straight to the formatter in turn.
+#[derive(Clone, Copy, Debug)]
+pub struct NonEscDisplay<'a>(&'a str);
+
+impl fmt::Display for NonEscDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.non_esc().try_for_each(|run| f.write_str(run))
+    }
 }
 
 /// Implement the trait for string slices.
@@ -26,182 +119,674 @@ impl<'a> NonEsc<'a> for &'a str {
     }
 }
 
-/// Current mode of the iterator.
-#[derive(PartialEq, Debug)]
-enum Mode {
-    Normal,
-    InEsc,
-    AwaitSt,
-    InOsc,
-    InCsi,
-    OscMaybeSt,
-    MaybeSt,
+/// Borrow a `String`, so `string.non_esc()` works without `as_str()` and leaves it usable.
+#[cfg(feature = "alloc")]
+impl<'a> NonEsc<'a> for &'a String {
+    fn non_esc(self) -> AnsiStripper<'a> {
+        AnsiStripper::new(self)
+    }
+}
+
+/// Borrow a `Cow`, whether it's borrowed or owned.
+#[cfg(feature = "alloc")]
+impl<'a> NonEsc<'a> for &'a Cow<'_, str> {
+    fn non_esc(self) -> AnsiStripper<'a> {
+        AnsiStripper::new(self)
+    }
+}
+
+/// Trait to strip out ANSI escape sequences from bytes that may not be UTF-8.
+pub trait NonEscBytes<'a> {
+    fn non_esc_bytes(self) -> AnsiByteStripper<'a>;
+}
+
+/// Implement the trait for byte slices.
+impl<'a> NonEscBytes<'a> for &'a [u8] {
+    fn non_esc_bytes(self) -> AnsiByteStripper<'a> {
+        AnsiStripper::with_options(self, Options::default())
+    }
+}
+
+/// Trait to pick out the ANSI escape sequences themselves, skipping the text around them.
+pub trait EscOnly<'a> {
+    fn esc_only(self) -> EscSequences<'a>;
+}
+
+impl<'a> EscOnly<'a> for &'a str {
+    fn esc_only(self) -> EscSequences<'a> {
+        EscSequences(AnsiStripper::new(self))
+    }
+}
+
+/// At each iteration, returns the next complete escape sequence that `non_esc` would remove.
+///
+/// Each one runs from its `ESC` or C1 introducer through its final byte or terminator. A
+/// sequence left incomplete at the end of the input isn't yielded.
+pub struct EscSequences<'a>(AnsiStripper<'a>);
+
+impl<'a> Iterator for EscSequences<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Span::Escape(seq) = self.0.next_span()? {
+                return Some(seq);
+            }
+        }
+    }
+}
+
+/// Trait to find where in a string each run of visible text lies.
+pub trait TextSpans<'a> {
+    fn spans(self) -> Spans<'a>;
+}
+
+impl<'a> TextSpans<'a> for &'a str {
+    fn spans(self) -> Spans<'a> {
+        Spans(AnsiStripper::new(self))
+    }
+}
+
+/// At each iteration, returns the next run that `non_esc` would, along with its byte range
+/// in the input.
+///
+/// The ranges are in order and never overlap, so a position in the stripped text can be
+/// mapped back to the input by counting through them.
+pub struct Spans<'a>(AnsiStripper<'a>);
+
+impl<'a> Iterator for Spans<'a> {
+    type Item = (Range<usize>, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let run = self.0.next()?;
+        // With the default options every run is a slice of the input.
+        let start = run.as_ptr() as usize - self.0.src.as_ptr() as usize;
+        Some((start..start + run.len(), run))
+    }
+}
+
+/// Trait to split a string into its text and escape segments, losing nothing.
+pub trait Segmented<'a> {
+    fn segments(self) -> Segments<'a>;
+}
+
+impl<'a> Segmented<'a> for &'a str {
+    fn segments(self) -> Segments<'a> {
+        Segments::new(self, Options::default())
+    }
+}
+
+/// A piece of the input: text that `non_esc` keeps, or bytes that it removes.
+///
+/// A slice of a `str` by default, or of a `[u8]` for segments of bytes.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Segment<'a, S: ?Sized = str> {
+    Text(&'a S),
+    /// A complete escape sequence, or what's left of a malformed or unterminated one.
+    Escape(&'a S),
+}
+
+impl<'a> Segment<'a> {
+    /// The segment's slice of the input, whichever kind it is.
+    pub fn as_str(&self) -> &'a str {
+        match *self {
+            Segment::Text(s) | Segment::Escape(s) => s,
+        }
+    }
+}
+
+/// At each iteration, returns the next `Segment` of the input, in order.
+///
+/// Concatenating every segment gives back the input exactly; concatenating just the text
+/// gives what `non_esc` yields.
+pub struct Segments<'a, S: ?Sized = str>
+where
+    &'a S: EscSource<'a>,
+{
+    src: &'a S,
+    pos: usize,
+    stripper: AnsiStripper<'a, &'a S>,
+    // A span found after a gap, yielded once the gap has been.
+    next: Option<Span<&'a S>>,
+    // A segment found after a group of escapes, yielded next.
+    peeked: Option<Segment<'a, S>>,
+}
+
+impl<'a, S: ?Sized> Segments<'a, S>
+where
+    &'a S: EscSource<'a>,
+{
+    pub(crate) fn new(src: &'a S, options: Options) -> Self {
+        Self {
+            src,
+            pos: 0,
+            stripper: AnsiStripper::with_options(src, options),
+            next: None,
+            peeked: None,
+        }
+    }
+
+    /// The next segment, before any grouping.
+    fn next_ungrouped(&mut self) -> Option<Segment<'a, S>> {
+        let len = self.src.as_bytes().len();
+        let Some(span) = self.next.take().or_else(|| self.stripper.next_span()) else {
+            // Whatever's left is an unterminated sequence.
+            let rest = self.src.slice(self.pos..len);
+            let empty = self.pos == len;
+            self.pos = len;
+            return (!empty).then_some(Segment::Escape(rest));
+        };
+        let (Span::Text(s) | Span::Escape(s)) = span;
+        let start = s.as_bytes().as_ptr() as usize - self.src.as_bytes().as_ptr() as usize;
+        if start > self.pos {
+            // The stripper skipped the start of a malformed sequence.
+            let gap = self.src.slice(self.pos..start);
+            self.pos = start;
+            self.next = Some(span);
+            return Some(Segment::Escape(gap));
+        }
+        self.pos = start + s.as_bytes().len();
+        Some(match span {
+            Span::Text(s) => Segment::Text(s),
+            Span::Escape(s) => Segment::Escape(s),
+        })
+    }
+}
+
+impl<'a, S: ?Sized> Iterator for Segments<'a, S>
+where
+    &'a S: EscSource<'a>,
+{
+    type Item = Segment<'a, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.peeked.take().or_else(|| self.next_ungrouped())?;
+        let Segment::Escape(seq) = first else {
+            return Some(first);
+        };
+        if !self.stripper.options.group_adjacent_escapes {
+            return Some(first);
+        }
+        // Segments are contiguous, so the group runs up to wherever the escapes stop.
+        let start = self.pos - seq.as_bytes().len();
+        let mut end = self.pos;
+        loop {
+            match self.next_ungrouped() {
+                Some(Segment::Escape(_)) => end = self.pos,
+                other => {
+                    self.peeked = other;
+                    break;
+                }
+            }
+        }
+        Some(Segment::Escape(self.src.slice(start..end)))
+    }
+}
+
+/// Strip all escape sequences from `input`, returning the visible text.
+///
+/// The same as collecting `input.non_esc()` into a `String`.
+#[cfg(feature = "alloc")]
+pub fn strip_ansi(input: &str) -> String {
+    input.non_esc().collect()
 }
 
 /// At each iteration, returns the next substring that doesn't contain an ANSI escape code.
-pub struct AnsiStripper<'a> {
-    src: &'a str,
-    char_indices: CharIndices<'a>,
+///
+/// Runs over `&str` by default; see `AnsiByteStripper` for `&[u8]`.
+pub struct AnsiStripper<'a, S: EscSource<'a> = &'a str> {
+    src: S,
+    // The units of `src[units_base..]`; `units_base` moves on when plain text is skipped.
+    units: S::Units,
+    units_base: usize,
     prev_index: usize,
     prev_char: Option<char>,
+    options: Options,
+    column: usize,
+    escapes: usize,
+    // Where the input ran out mid-sequence: the sequence start and the mode it was in.
+    unterminated: Option<(usize, Mode)>,
+    // With `line_color_simplify`: whether this line's colour has been opened, and the rest
+    // of a text run held back while its reset is yielded.
+    line_colored: bool,
+    held: Option<S>,
+    // With `osc8_as_markdown`: the URI of the open link, and the Markdown still to yield for
+    // a link just opened or closed.
+    link: Option<S>,
+    markdown: [Option<S>; 4],
+    eight_bit: bool,
+    // The whole source is ASCII, so its units needn't be decoded.
+    ascii: bool,
+    // Set by `resume_in`: the mode to start in, carrying on a sequence begun before `src`.
+    resume: Option<Mode>,
+    // Whether the span just returned, or the sequence left unterminated, is that sequence
+    // carried on from before `src`.
+    pub(crate) continued: bool,
 }
 
+/// An `AnsiStripper` over bytes, yielding the byte runs that aren't part of an escape code.
+///
+/// It's the same generic stripper as for `&str`, so on UTF-8 input it yields the same spans.
+pub type AnsiByteStripper<'a> = AnsiStripper<'a, &'a [u8]>;
+
 /// Create an AnsiStripper against a string slice.
 impl<'a> AnsiStripper<'a> {
     fn new(src: &'a str) -> Self {
+        Self::with_options(src, Options::default())
+    }
+
+    /// The number of chars in the remaining visible text, without collecting it.
+    pub fn visible_len(self) -> usize {
+        self.map(|run| run.chars().count()).sum()
+    }
+}
+
+impl<'a, S: EscSource<'a>> AnsiStripper<'a, S> {
+    pub(crate) fn with_options(src: S, options: Options) -> Self {
+        let eight_bit = options.control_mode.unwrap_or(S::CONTROL_MODE) == ControlMode::EightBit;
+        let ascii = src.as_bytes().is_ascii();
         Self {
             src,
-            char_indices: src.char_indices(),
+            units: if ascii {
+                src.ascii_units()
+            } else {
+                src.units()
+            },
+            units_base: 0,
             prev_index: 0,
             prev_char: None,
+            options,
+            column: 0,
+            escapes: 0,
+            unterminated: None,
+            line_colored: false,
+            held: None,
+            link: None,
+            markdown: [None; 4],
+            eight_bit,
+            ascii,
+            resume: None,
+            continued: false,
         }
     }
-}
 
-impl<'a> Iterator for AnsiStripper<'a> {
-    type Item = &'a str;
+    /// Start in `mode` rather than in text, as if the source followed the part of a sequence
+    /// that put the state machine in it.
+    ///
+    /// If the first span is that sequence, or it's left unterminated, `continued` says so and
+    /// the span only holds the part of it in the source.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn resume_in(&mut self, mode: Mode) {
+        self.resume = (mode != Mode::Normal).then_some(mode);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut curr_index: usize;
-        let mut curr_char: char;
+    /// Restart iteration from the beginning of the source.
+    pub fn reset(&mut self) {
+        self.units = self.units_from(0);
+        self.units_base = 0;
+        self.prev_index = 0;
+        self.prev_char = None;
+        self.column = 0;
+        self.escapes = 0;
+        self.unterminated = None;
+        self.line_colored = false;
+        self.held = None;
+        self.link = None;
+        self.markdown = [None; 4];
+        self.resume = None;
+        self.continued = false;
+    }
 
-        // Get the first char (and index) to work with in this iteration.
-        // If there was (index, Option<char>) left over from last iteration, use that.
-        if self.prev_char.is_some() {
-            // there were leftovers from last iteration...
-            (curr_index, curr_char) = (self.prev_index, self.prev_char.unwrap());
-            self.prev_char = None;
-        } else {
-            // There were no leftovers to consider, so pull the next char...
-            (curr_index, curr_char) = match self.char_indices.next() {
-                Some((i, c)) => (i, c),
-                // No leftovers and nothing left: just exit.
-                None => return None,
+    /// Collect the kept spans into any collection, e.g. `String` or `Vec<&str>`.
+    pub fn strip_collect<B: FromIterator<S>>(self) -> B {
+        self.collect()
+    }
+
+    /// Call `f` on each run the iterator would yield, in the same order.
+    ///
+    /// One loop over the state machine with `f` inlined into it, rather than a call to
+    /// `next` per run.
+    pub fn for_each<F: FnMut(S)>(mut self, mut f: F) {
+        while let Some(span) = self.next_kept() {
+            match span {
+                Span::Text(s) | Span::Escape(s) => f(s),
             }
+        }
+    }
+
+    /// The number of complete escape sequences removed so far.
+    pub fn escape_count(&self) -> usize {
+        self.escapes
+    }
+
+    /// Account for a yielded text run so tabs can be padded to the next tab stop.
+    fn text(&mut self, s: S) -> S {
+        if self.options.pad_removed {
+            // Count chars as the bytes that don't continue a UTF-8 sequence.
+            let bytes = s.as_bytes();
+            let chars = |b: &[u8]| b.iter().filter(|&&b| b & 0xc0 != 0x80).count();
+            self.column = match bytes.iter().rposition(|&b| b == b'\n') {
+                Some(i) => chars(&bytes[i + 1..]),
+                None => self.column + chars(bytes),
+            };
+        }
+        s
+    }
+
+    /// `transition`, except that C1 controls are text unless 8-bit controls are recognised,
+    /// that with `esc_byte` that char is ESC and ESC is text, and that `csi_final_range`
+    /// decides which chars end a CSI.
+    fn step(&self, mode: Mode, c: char) -> (Mode, Action) {
+        // Any other non-ASCII char takes the same path as text.
+        const TEXT: char = '\u{a0}';
+        let c = match self.options.esc_byte.map(char::from) {
+            Some(esc) if c == esc => ESC,
+            Some(_) if c == ESC => TEXT,
+            _ if !self.eight_bit && ('\u{80}'..='\u{9f}').contains(&c) => TEXT,
+            _ => c,
         };
+        match transition(mode, c) {
+            (Mode::Normal, Action::EndSeq | Action::Keep)
+                if matches!(mode, Mode::InCsi | Mode::InCsiIntermediate) =>
+            {
+                if self.options.csi_final_range.accepts(c) {
+                    (Mode::Normal, Action::EndSeq)
+                } else {
+                    (Mode::Normal, Action::Keep)
+                }
+            }
+            next => next,
+        }
+    }
+
+    /// The byte that stands for ESC.
+    fn esc(&self) -> u8 {
+        self.options.esc()
+    }
 
-        let mut start_index = curr_index;
-        let mut end_index = curr_index + curr_char.len_utf8();
-        let mut mode = if curr_char == ESC {
-            Mode::InEsc
+    /// The next unit and its byte offset in `src`.
+    fn next_unit(&mut self) -> Option<(usize, char)> {
+        let (i, c) = self.units.next()?;
+        Some((self.units_base + i, c))
+    }
+
+    /// Skip the plain text from byte `start` in one step, returning where it ends and leaving
+    /// the units to go on from there.
+    ///
+    /// It ends at the first byte that the state machine might not just keep: the ESC, a C1
+    /// introducer if those are recognised, or a tab if tabs are padded. All of those are
+    /// ASCII or lead a UTF-8 char, so the end is always on a char boundary.
+    fn skip_plain(&mut self, start: usize) -> usize {
+        let bytes = self.src.as_bytes();
+        let esc = self.esc();
+        let tab = if self.options.pad_removed {
+            TAB as u8
         } else {
-            Mode::Normal
+            esc
         };
+        let end = start + S::plain_len(&bytes[start..], [esc, tab], self.eight_bit);
+        if end > start {
+            self.units = self.units_from(end);
+            self.units_base = end;
+        }
+        end
+    }
+
+    /// The units of `src` from byte `start` on, without decoding if it's all ASCII.
+    fn units_from(&self, start: usize) -> S::Units {
+        let rest = self.src.slice(start..self.src.as_bytes().len());
+        if self.ascii {
+            rest.ascii_units()
+        } else {
+            rest.units()
+        }
+    }
+
+    /// The spaces standing in for a tab at the current column.
+    fn tab_padding(&mut self) -> S {
+        let width = TAB_WIDTH - self.column % TAB_WIDTH;
+        self.column += width;
+        S::from_static(&SPACES[..width])
+    }
+}
+
+/// A piece of the source: either visible text or one complete escape sequence.
+#[derive(PartialEq, Debug)]
+pub(crate) enum Span<S> {
+    Text(S),
+    Escape(S),
+}
+
+impl<'a, S: EscSource<'a>> Iterator for AnsiStripper<'a, S> {
+    type Item = S;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_kept()? {
+            Span::Text(s) | Span::Escape(s) => Some(s),
+        }
+    }
+
+    /// At most one item per byte left, plus any already due without reading more.
+    ///
+    /// Each run, kept sequence or tab's padding uses up at least a byte of input, as do the
+    /// resets and Markdown that stand in for removed sequences. Only a final reset or link
+    /// close can come with no input left, and those are counted as due.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (_, units) = self.units.size_hint();
+        let queued = self.markdown.iter().flatten().count();
+        let due = usize::from(self.prev_char.is_some())
+            + usize::from(self.held.is_some())
+            + usize::from(self.line_colored)
+            + queued
+            + if self.link.is_some() { 3 } else { 0 };
+        (0, units.map(|units| units + due))
+    }
+}
 
+impl<'a, S: EscSource<'a>> AnsiStripper<'a, S> {
+    /// The next text run or escape sequence to output, counting the escapes removed.
+    pub(crate) fn next_kept(&mut self) -> Option<Span<S>> {
+        if self.options.line_color_simplify {
+            return self.next_simplified();
+        }
         loop {
-            // Test and assign the next character.
-            (curr_index, curr_char) = match self.char_indices.next() {
-                Some((i, c)) => (i, c),
-                None => {
-                    return match (mode, end_index > start_index) {
-                        (Mode::Normal, true) => Some(&self.src[start_index..end_index]),
-                        _ => None,
-                    }
-                }
+            // Markdown is passed through as kept escapes are, untouched by `map_text`.
+            if let Some(md) = self.markdown.iter_mut().find_map(Option::take) {
+                return Some(Span::Escape(md));
+            }
+            let Some(span) = self.next_span() else {
+                self.link?;
+                // Close the link still open at the end.
+                self.markdown_link(S::from_static(""));
+                continue;
             };
-
-            end_index = curr_index + curr_char.len_utf8();
-
-            match mode {
-                Mode::Normal => {
-                    if curr_char == ESC {
-                        // We're moving from Normal to InEsc...
-                        self.prev_index = curr_index;
-                        self.prev_char = Some(curr_char);
-                        if curr_index > start_index {
-                            // If there's a string to yield then yield it...
-                            return Some(&self.src[start_index..curr_index]);
-                        } else {
-                            // ... otherwise just move to the next mode.
-                            mode = Mode::InEsc;
+            match span {
+                Span::Escape(seq) if !self.options.keeps(seq.as_bytes()) => {
+                    self.escapes += 1;
+                    if self.options.osc8_as_markdown {
+                        if let Some(uri) = hyperlink_uri(seq.as_bytes()) {
+                            self.markdown_link(seq.slice(uri));
                         }
+                        continue;
+                    }
+                    match self.options.preserved_uri(seq.as_bytes()) {
+                        Some(uri) if !uri.is_empty() => return Some(Span::Text(seq.slice(uri))),
+                        _ => {}
                     }
                 }
+                span => return Some(span),
+            }
+        }
+    }
 
-                // In the last iteration we had the initial ESC of an escape code.
-                Mode::InEsc => {
-                    mode = match curr_char {
-                        // For these we just await the ST (String Terminator)
-                        DCS => Mode::AwaitSt,
-                        SOC => Mode::AwaitSt,
-                        PM => Mode::AwaitSt,
-                        APC => Mode::AwaitSt,
-                        // OSC (Operating System Command) has some special handling for BEL or ST
-                        OSC => Mode::InOsc,
-                        // Next is a CSI (Control Sequence Indicator)
-                        CSI => Mode::InCsi,
-                        // Another ESC?
-                        ESC => {
-                            // Skip the last one
-                            start_index = curr_index;
-                            Mode::InEsc
-                        }
-                        // Not really defined...
-                        _ => {
-                            // Just ignore the ESC I guess?
-                            start_index = curr_index;
-                            Mode::Normal
-                        }
+    /// Queue the Markdown for an OSC 8 to `uri`: `[` to open a link, or `](uri)` to close the
+    /// open one if `uri` is empty. Opening a link while one is open closes that one first.
+    fn markdown_link(&mut self, uri: S) {
+        let mut queue = self.markdown.iter_mut();
+        if let Some(open) = self.link.take() {
+            for md in [S::from_static("]("), open, S::from_static(")")] {
+                *queue.next().unwrap() = Some(md);
+            }
+        }
+        if !uri.as_bytes().is_empty() {
+            *queue.next().unwrap() = Some(S::from_static("["));
+            self.link = Some(uri);
+        }
+    }
+
+    /// `next_kept` for `line_color_simplify`: one opening SGR per line, reset before its
+    /// newline.
+    fn next_simplified(&mut self) -> Option<Span<S>> {
+        loop {
+            let span = match self.held.take() {
+                Some(rest) => Span::Text(rest),
+                None => match self.next_span() {
+                    Some(span) => span,
+                    None if self.line_colored => {
+                        self.line_colored = false;
+                        return Some(Span::Escape(S::from_static(SGR_RESET)));
+                    }
+                    None => return None,
+                },
+            };
+            match span {
+                Span::Text(s) if self.line_colored => {
+                    let bytes = s.as_bytes();
+                    let Some(nl) = bytes.iter().position(|&b| b == b'\n') else {
+                        return Some(Span::Text(s));
                     };
+                    self.held = Some(s.slice(nl..bytes.len()));
+                    if nl > 0 {
+                        return Some(Span::Text(s.slice(0..nl)));
+                    }
+                    self.line_colored = false;
+                    return Some(Span::Escape(S::from_static(SGR_RESET)));
                 }
-
-                Mode::InCsi => {
-                    // https://w.wiki/Bk2X#Control_Sequence_Introducer_commands
-                    if curr_char >= '@' && curr_char <= '~' {
-                        // got the "final byte": switch back to Normal mode.
-                        start_index = end_index;
-                        mode = Mode::Normal;
+                Span::Text(s) => return Some(Span::Text(s)),
+                Span::Escape(seq) if is_sgr(seq.as_bytes(), ESC as u8) => {
+                    if !self.line_colored && self.options.opens_line_color(seq.as_bytes()) {
+                        self.line_colored = true;
+                        return Some(Span::Escape(seq));
                     }
+                    self.escapes += 1;
                 }
-
-                Mode::InOsc => {
-                    mode = match curr_char {
-                        // BEL is magic end marker for OSC too.
-                        BEL => {
-                            start_index = end_index;
-                            Mode::Normal
-                        }
-                        // Maybe about to get ST end?
-                        ESC => Mode::OscMaybeSt,
-                        _ => Mode::InOsc,
-                    };
+                Span::Escape(seq) if self.options.keeps(seq.as_bytes()) => {
+                    return Some(Span::Escape(seq))
                 }
+                Span::Escape(_) => self.escapes += 1,
+            }
+        }
+    }
 
-                // Are we waiting on a String Termination (ST) char?
-                Mode::AwaitSt => {
-                    mode = match curr_char {
-                        ESC => Mode::MaybeSt,
-                        _ => Mode::AwaitSt,
-                    };
+    /// The next text run or complete escape sequence.
+    pub(crate) fn next_span(&mut self) -> Option<Span<S>> {
+        let mut curr_index: usize;
+        let mut curr_char: char;
+        let mut start_index: usize;
+        let mut end_index: usize;
+        let mut mode: Mode;
+
+        if let Some(resumed) = self.resume.take() {
+            // Carry on with the sequence begun before `src`, none of which is in `src` yet.
+            self.continued = true;
+            (start_index, end_index, mode) = (0, 0, resumed);
+        } else {
+            self.continued = false;
+
+            // Get the first char (and index) to work with in this iteration.
+            // If there was (index, Option<char>) left over from last iteration, use that.
+            if self.prev_char.is_some() {
+                // there were leftovers from last iteration...
+                (curr_index, curr_char) = (self.prev_index, self.prev_char.unwrap());
+                self.prev_char = None;
+            } else {
+                // There were no leftovers to consider, so pull the next char...
+                (curr_index, curr_char) = match self.next_unit() {
+                    Some((i, c)) => (i, c),
+                    // No leftovers and nothing left: just exit.
+                    None => return None,
                 }
+            };
 
-                Mode::OscMaybeSt => {
-                    mode = match curr_char {
-                        // Got ST end: back to normal
-                        ST_CHAR | BEL => {
-                            start_index = end_index;
-                            Mode::Normal
-                        }
-                        ESC => {
-                            // Another ESC? Do nothing.
-                            Mode::OscMaybeSt
-                        }
-                        // Undefined? Wait for next time?
-                        _ => Mode::InOsc,
-                    };
+            if curr_char == TAB && self.options.pad_removed {
+                return Some(Span::Text(self.tab_padding()));
+            }
+
+            start_index = curr_index;
+            end_index = curr_index + S::unit_len(curr_char);
+            (mode, _) = self.step(Mode::Normal, curr_char);
+        }
+
+        loop {
+            if mode == Mode::Normal {
+                end_index = self.skip_plain(end_index);
+            }
+
+            // Test and assign the next character.
+            (curr_index, curr_char) = match self.next_unit() {
+                Some((i, c)) => (i, c),
+                None => {
+                    if mode != Mode::Normal {
+                        // Ran out mid-sequence: remember where it began.
+                        self.unterminated = Some((start_index, mode));
+                        return self.options.lenient.then(|| {
+                            Span::Text(self.text(self.src.slice(start_index..end_index)))
+                        });
+                    }
+                    return (end_index > start_index)
+                        .then(|| Span::Text(self.text(self.src.slice(start_index..end_index))));
                 }
+            };
 
-                Mode::MaybeSt => {
-                    mode = match curr_char {
-                        ST_CHAR => {
-                            start_index = end_index;
-                            Mode::Normal
-                        }
-                        // Nope: back to waiting
-                        _ => Mode::AwaitSt,
+            end_index = curr_index + S::unit_len(curr_char);
+
+            if mode == Mode::Normal && curr_char == TAB && self.options.pad_removed {
+                // Yield the text so far and pad out the tab next time.
+                self.prev_index = curr_index;
+                self.prev_char = Some(curr_char);
+                return Some(Span::Text(
+                    self.text(self.src.slice(start_index..curr_index)),
+                ));
+            }
+
+            let (next_mode, action) = match (mode, curr_char) {
+                // Linux console palette set (fixed length) and reset (no payload)
+                (Mode::InOsc, LINUX_PALETTE_SET | LINUX_PALETTE_RESET)
+                    if self.options.linux_console
+                        && !self.continued
+                        && self.src.slice(start_index..curr_index).as_bytes()
+                            == [self.esc(), b']'] =>
+                {
+                    if curr_char == LINUX_PALETTE_SET {
+                        (Mode::InPalette(7), Action::Drop)
+                    } else {
+                        (Mode::Normal, Action::EndSeq)
                     }
                 }
+                _ => self.step(mode, curr_char),
+            };
+
+            match action {
+                Action::StartSeq if mode == Mode::Normal => {
+                    // We're moving from Normal to InEsc: yield the text so far first.
+                    self.prev_index = curr_index;
+                    self.prev_char = Some(curr_char);
+                    return Some(Span::Text(
+                        self.text(self.src.slice(start_index..curr_index)),
+                    ));
+                }
+                // Malformed: drop what we had and treat this char afresh.
+                Action::StartSeq | Action::Keep if mode != Mode::Normal => {
+                    start_index = curr_index;
+                    self.continued = false;
+                }
+                // The whole sequence is src[start_index..end_index].
+                Action::EndSeq => {
+                    return Some(Span::Escape(self.src.slice(start_index..end_index)))
+                }
+                _ => {}
             }
+            mode = next_mode;
         }
     }
 }
@@ -220,6 +805,85 @@ mod tests {
         standard_test("", vec![])
     }
 
+    #[test]
+    fn non_esc_bytes() {
+        let sample: &[u8] = b"\xff\xfe\x1b[1mbold\x1b[0m\x80";
+        let spans: Vec<&[u8]> = sample.non_esc_bytes().collect();
+        assert_eq!(vec![&b"\xff\xfe"[..], b"bold", b"\x80"], spans);
+    }
+
+    #[test]
+    fn non_esc_bytes_across_char_boundary() {
+        // The escape sits between the bytes of a UTF-8 char; nothing is decoded, so both halves
+        // survive and rejoin.
+        let mut sample = "é".as_bytes()[..1].to_vec();
+        sample.extend_from_slice(b"\x1b[31m");
+        sample.extend_from_slice(&"é".as_bytes()[1..]);
+        let joined: Vec<u8> = sample
+            .as_slice()
+            .non_esc_bytes()
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!("é".as_bytes(), joined);
+    }
+
+    #[test]
+    fn plain_runs_skipped_whole() {
+        // '°' and '£' lead with the same byte as a C1 control, so the skip stops at each.
+        standard_test("25°C £3 \u{9b}1mhot", vec!["25°C £3 ", "hot"]);
+        standard_test("aaaa\x1b[0mbbbb", vec!["aaaa", "bbbb"]);
+        let padded: String = AnsiStripperBuilder::new()
+            .pad_removed(true)
+            .build("abc\tdefghij\tk")
+            .collect();
+        assert_eq!("abc     defghij k", padded);
+    }
+
+    #[test]
+    fn non_esc_bytes_pass_non_ascii_through() {
+        let sample: Vec<u8> = (0x80..=0xff).chain(*b"\x1b]0;t\x07").collect();
+        let joined: Vec<u8> = sample
+            .as_slice()
+            .non_esc_bytes()
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!((0x80..=0xff).collect::<Vec<u8>>(), joined);
+        let escape_byte: Vec<&[u8]> = b"a\x1b\xc3b".as_slice().non_esc_bytes().collect();
+        assert_eq!(vec![&b"a"[..], b"\xc3b"], escape_byte);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn non_esc_for_owned_strings() {
+        let owned = format!("a{ESC}[1mb");
+        let from_string: Vec<&str> = owned.non_esc().collect();
+        let from_ref: Vec<&str> = (&owned).non_esc().collect();
+        assert_eq!(vec!["a", "b"], from_string);
+        assert_eq!(from_string, from_ref);
+        // Only borrowed, so still usable.
+        assert_eq!(6, owned.len());
+
+        let borrowed: Cow<str> = Cow::Borrowed("c\x1b[0md");
+        let from_cow: Cow<str> = Cow::Owned(owned.clone());
+        assert_eq!(vec!["c", "d"], borrowed.non_esc().collect::<Vec<_>>());
+        assert_eq!(vec!["a", "b"], from_cow.non_esc().collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn strip_ansi_collects() {
+        assert_eq!("Hello, world!", strip_ansi("Hello, \x1b[0mworld\x1b[123m!"));
+        assert_eq!("", strip_ansi(""));
+        assert_eq!("", strip_ansi(&format!("{ESC}[0m{ESC}]0;t{BEL}{ESC}[")));
+        let sample = format!("a{ESC}[1mb{ESC}P{ESC}c");
+        assert_eq!(
+            sample.as_str().non_esc().collect::<String>(),
+            strip_ansi(&sample)
+        );
+    }
+
     #[test]
     fn single_good() {
         standard_test("a", vec!["a"])
@@ -263,6 +927,48 @@ mod tests {
         standard_test(&format!("{ESC}{ESC}[m"), vec![])
     }
 
+    #[test]
+    fn leading_esc_never_leaks() {
+        // A leading ESC starts a sequence straight away, with nothing stashed.
+        standard_test(&format!("{ESC}[0mx"), vec!["x"]);
+        standard_test(&format!("{ESC}abc"), vec!["bc"]);
+        standard_test(&format!("{ESC}{ESC}abc"), vec!["bc"]);
+        standard_test(&format!("{ESC}\nabc"), vec!["\nabc"]);
+        standard_test(&format!("{ESC}éabc"), vec!["éabc"]);
+        standard_test(&format!("{ESC}"), vec![]);
+    }
+
+    #[test]
+    fn repeated_esc_prefixes() {
+        // Each stray ESC is dropped as the next one restarts the sequence. `ESC a` is itself
+        // a complete Fs escape, so it takes the `a` with it.
+        standard_test(&format!("{ESC}{ESC}{ESC}abc"), vec!["bc"]);
+        standard_test(&format!("{ESC}{ESC}{ESC}{ESC}[0m"), vec![]);
+        standard_test(&format!("{ESC}{ESC}{ESC}\nabc"), vec!["\nabc"]);
+    }
+
+    #[test]
+    fn long_esc_runs_are_linear() {
+        // Quadratic rescanning would take far too long at this length.
+        let run = ESC.to_string().repeat(200_000);
+        standard_test(&format!("{run}[0mx"), vec!["x"]);
+        standard_test(&format!("a{run}éabc"), vec!["a", "éabc"]);
+        standard_test(&run, vec![]);
+    }
+
+    #[test]
+    fn stashed_esc_never_leaks() {
+        // After text, the ESC is stashed while the text is yielded, then picked up again.
+        standard_test(&format!("a{ESC}[0mx"), vec!["a", "x"]);
+        standard_test(&format!("a{ESC}\nb"), vec!["a", "\nb"]);
+        standard_test(&format!("a{ESC}"), vec!["a"]);
+        let padded: String = AnsiStripperBuilder::new()
+            .pad_removed(true)
+            .build(&format!("\t{ESC}éx"))
+            .collect();
+        assert_eq!("        éx", padded);
+    }
+
     #[test]
     fn back_loose_esc_single_csi() {
         standard_test(&format!("{ESC}[m{ESC}"), vec![])
@@ -283,6 +989,15 @@ mod tests {
         standard_test(&format!("{ESC}[mn{ESC}[m"), vec!["n"])
     }
 
+    #[test]
+    fn csi_private_prefix_m() {
+        standard_test(&format!("{ESC}[>4;2m"), vec![]);
+        standard_test(
+            &format!("a{ESC}[>4mb{ESC}[=1mc{ESC}[?1md"),
+            vec!["a", "b", "c", "d"],
+        );
+    }
+
     #[test]
     fn char_csi_char() {
         standard_test(&format!("o{ESC}[mn"), vec!["o", "n"])
@@ -315,12 +1030,487 @@ mod tests {
 
     #[test]
     fn osc_errant_esc_st() {
-        standard_test(&format!("n{ESC}]{ESC}{ST}m"), vec!["n", "m"])
+        // An ESC in the payload that isn't the start of ST goes back to consuming the payload.
+        standard_test(&format!("n{ESC}]0;a{ESC}bc{ST}m"), vec!["n", "m"])
     }
 
     #[test]
     fn osc_errant_esc_bel() {
         standard_test(&format!("n{ESC}]{ESC}{BEL}m"), vec!["n", "m"])
     }
-}
 
+    #[test]
+    fn osc_c1_st() {
+        standard_test("n\x1b]0;title\u{9c}m", vec!["n", "m"]);
+        standard_test(&format!("n{ESC}]0;a{ESC}\u{9c}m"), vec!["n", "m"]);
+        // In bytes, and with 7-bit controls, 0x9c is text and the OSC runs to the end.
+        let bytes: Vec<&[u8]> = b"n\x1b]0;title\x9cm".as_slice().non_esc_bytes().collect();
+        assert_eq!(vec![b"n"], bytes);
+    }
+
+    #[test]
+    fn osc_backslash_without_esc() {
+        // A `\` only ends an OSC as the second byte of `ESC \`.
+        standard_test(&format!("n{ESC}]0;C:\\dir\\{BEL}m"), vec!["n", "m"]);
+        standard_test(&format!("n{ESC}]0;a\\b{ST}m"), vec!["n", "m"]);
+        standard_test(&format!("n{ESC}]0;a\\b"), vec!["n"]);
+    }
+
+    #[test]
+    fn osc_errant_esc_in_payload() {
+        standard_test(&format!("{ESC}]0;a{ESC}bc{BEL}x"), vec!["x"]);
+        standard_test(&format!("{ESC}]0;a{ESC}{ESC}bc{BEL}x"), vec!["x"]);
+        standard_test(&format!("{ESC}]0;a{ESC}]b{ESC}[c{BEL}x"), vec!["x"]);
+        standard_test(&format!("{ESC}]0;{ESC}\n{ESC}b{ESC}{ESC}{ST}x"), vec!["x"]);
+    }
+
+    #[test]
+    fn function_key() {
+        standard_test(&format!("{ESC}[5~"), vec![])
+    }
+
+    #[test]
+    fn function_key_between_chars() {
+        standard_test(&format!("a{ESC}[3~b"), vec!["a", "b"])
+    }
+
+    #[test]
+    fn function_keys_at_eof() {
+        standard_test(&format!("a{ESC}[1~{ESC}[4~"), vec!["a"])
+    }
+
+    #[test]
+    fn decrqss_request() {
+        standard_test(&format!("a{ESC}P$qm{ESC}{ST_CHAR}b"), vec!["a", "b"])
+    }
+
+    #[test]
+    fn decrqss_response() {
+        standard_test(&format!("a{ESC}P1$r0;1m{ESC}{ST_CHAR}b"), vec!["a", "b"])
+    }
+
+    #[test]
+    fn dcs_double_esc_before_st() {
+        standard_test("n\x1bP x\x1b\x1b\\m", vec!["n", "m"]);
+        standard_test("n\x1b_x\x1b\x1b\x1b\\m", vec!["n", "m"]);
+    }
+
+    #[test]
+    fn string_with_embedded_escape() {
+        // `ESC 7` (save cursor) is data in the payload, not the start of ST.
+        standard_test("a\x1bPdata\x1b7more\x1b\\b", vec!["a", "b"]);
+        standard_test("a\x1b_x\x1b7\x1b8\x1b[0m\x1b\\b", vec!["a", "b"]);
+        standard_test("a\x1b^\x1b7\x1b\x1b\\b", vec!["a", "b"]);
+        let bytes: Vec<&[u8]> = b"a\x1bXdata\x1b7more\x1b\\b".non_esc_bytes().collect();
+        assert_eq!(vec![&b"a"[..], b"b"], bytes);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn string_with_embedded_escape_split() {
+        // Split right after the embedded escape, and again between ESC and `\`.
+        let mut stripper = StatefulStripper::new();
+        assert_eq!("a", stripper.push("a\x1bPdata\x1b7"));
+        assert_eq!("", stripper.push("more\x1b"));
+        assert_eq!("b", stripper.push("\\b"));
+    }
+
+    #[test]
+    fn decrqss_interior_esc() {
+        standard_test(&format!("a{ESC}P1$r{ESC}q{ESC}{ST_CHAR}b"), vec!["a", "b"])
+    }
+
+    #[test]
+    fn counts_escapes() {
+        let sample = format!("a{ESC}[0mb{ESC}]0;t{BEL}{ESC}Pq{ESC}{ST_CHAR}{ESC}{ESC}[");
+        let mut stripper = sample.as_str().non_esc();
+        assert_eq!(vec!["a", "b"], stripper.by_ref().collect::<Vec<&str>>());
+        assert_eq!(3, stripper.escape_count());
+        stripper.reset();
+        assert_eq!(0, stripper.escape_count());
+    }
+
+    #[test]
+    fn sub_slices_match_standalone() {
+        let big = format!("lead {ESC}[1mbold{ESC}[0m mid{ESC}]0;t{BEL}tail");
+        let esc_at: Vec<usize> = big.match_indices(ESC).map(|(i, _)| i).collect();
+        // Just before each ESC, just after it, and in the middle of plain text.
+        let mut cuts = vec![0, 2, 7, big.len()];
+        for i in esc_at {
+            cuts.extend([i, i + 1]);
+        }
+        for &start in &cuts {
+            for &end in &cuts {
+                if start > end {
+                    continue;
+                }
+                let slice = &big[start..end];
+                let owned = slice.to_string();
+                let from_slice: Vec<&str> = slice.non_esc().collect();
+                let standalone: Vec<&str> = owned.as_str().non_esc().collect();
+                assert_eq!(standalone, from_slice, "slice {start}..{end}");
+            }
+        }
+    }
+
+    #[test]
+    fn sub_slice_ending_before_esc() {
+        let big = format!("ab{ESC}[0mcd");
+        standard_test(&big[..2], vec!["ab"]);
+        standard_test(&big[2..], vec!["cd"]);
+        standard_test(&big[1..3], vec!["b"]);
+    }
+
+    #[test]
+    fn esc_after_four_byte_char() {
+        standard_test(&format!("😀{ESC}[0m😀"), vec!["😀", "😀"])
+    }
+
+    #[test]
+    fn four_byte_char_between_escapes() {
+        standard_test(&format!("{ESC}[0m😀{ESC}[0m"), vec!["😀"])
+    }
+
+    #[test]
+    fn four_byte_chars_around_every_escape_kind() {
+        standard_test(
+            &format!("😀{ESC}]0;😀{BEL}漢{ESC}P😀{ESC}{ST_CHAR}é{ESC}[1;2m😀"),
+            vec!["😀", "漢", "é", "😀"],
+        )
+    }
+
+    #[test]
+    fn four_byte_char_after_bare_esc() {
+        standard_test(&format!("😀{ESC}"), vec!["😀"]);
+        standard_test(&format!("{ESC}😀x"), vec!["😀x"]);
+    }
+
+    #[test]
+    fn sequences_span_newlines() {
+        standard_test(
+            &format!("a{ESC}Pq#0;2;0;0;0\n#0~~@@\n-{ESC}{ST_CHAR}b\n"),
+            vec!["a", "b\n"],
+        );
+        standard_test(&format!("a{ESC}]0;two\nlines{BEL}b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn strip_collect_into_collections() {
+        let sample = format!("Hello, {ESC}{CSI}0mworld{ESC}{CSI}123m!");
+        let joined: String = sample.as_str().non_esc().strip_collect();
+        let spans: Vec<&str> = sample.as_str().non_esc().strip_collect();
+        let cow: std::borrow::Cow<str> = sample.as_str().non_esc().strip_collect();
+        assert_eq!("Hello, world!", joined);
+        assert_eq!(vec!["Hello, ", "world", "!"], spans);
+        assert_eq!("Hello, world!", cow);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("hi", format!("{}", "\x1b[31mhi\x1b[0m".non_esc_display()));
+        let owned = String::from("x\x1b]0;t\x07y");
+        assert_eq!("xy", owned.non_esc_display().to_string());
+        let mut out = String::new();
+        core::fmt::write(
+            &mut out,
+            format_args!("{}!", "\x1b[2Jclear".non_esc_display()),
+        )
+        .unwrap();
+        assert_eq!("clear!", out);
+    }
+
+    #[test]
+    fn size_hint_bounds_items() {
+        let sample = "a\x1b[1mb\tc\x1b]0;t\x07d";
+        let mut stripper = sample.non_esc();
+        assert_eq!((0, Some(sample.len())), stripper.size_hint());
+        // The ESC that ended the first run has been read, but not yet used.
+        assert_eq!(Some("a"), stripper.next());
+        assert_eq!((0, Some(sample.len() - 1)), stripper.size_hint());
+        assert_eq!(2, stripper.by_ref().count());
+        assert_eq!((0, Some(0)), stripper.size_hint());
+        assert_eq!((0, Some(0)), "".non_esc().size_hint());
+    }
+
+    #[test]
+    fn for_each_matches_iterator() {
+        let samples = [
+            "",
+            "plain",
+            "a\x1b[1mb\x1b[0m",
+            "\x1b]0;title\x07x\x1bPq#0\x1b\\y\x1b[",
+            "\t25°C\u{9b}2Jé\x1b(B\n",
+        ];
+        for sample in samples {
+            let mut runs = Vec::new();
+            sample.non_esc().for_each(|run| runs.push(run));
+            assert_eq!(sample.non_esc().collect::<Vec<_>>(), runs, "{sample:?}");
+
+            let mut runs = Vec::new();
+            for_each_run(sample, |run| runs.push(run.len()));
+            let lens: Vec<usize> = sample.non_esc().map(str::len).collect();
+            assert_eq!(lens, runs);
+        }
+        let mut padded = String::new();
+        AnsiStripperBuilder::new()
+            .pad_removed(true)
+            .keep_sgr(true)
+            .build("a\tb\x1b[1mc")
+            .for_each(|run| padded.push_str(run));
+        assert_eq!("a       b\x1b[1mc", padded);
+    }
+
+    #[test]
+    fn stray_st() {
+        standard_test(&format!("a{ESC}{ST_CHAR}b"), vec!["a", "b"]);
+        standard_test(&format!("{ESC}{ST_CHAR}"), vec![]);
+        standard_test(&format!("a{ESC}{ESC}{ST_CHAR}b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn osc_4_multiple_pairs() {
+        let osc = format!("{ESC}]4;1;rgb:ff/00/00;2;rgb:00/ff/00;3;#0000ff");
+        standard_test(&format!("a{osc}{ESC}{ST_CHAR}b"), vec!["a", "b"]);
+        standard_test(&format!("a{osc}{BEL}b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn final_multibyte_run_after_escape() {
+        standard_test(&format!("{ESC}[0m😀"), vec!["😀"])
+    }
+
+    #[test]
+    fn final_run_emitted_once() {
+        for (sample, expected) in [
+            ("a".to_string(), vec!["a"]),
+            ("😀".to_string(), vec!["😀"]),
+            (format!("a{ESC}"), vec!["a"]),
+            (format!("a{ESC}["), vec!["a"]),
+            (format!("😀{ESC}]0;t"), vec!["😀"]),
+            (format!("a{ESC}P"), vec!["a"]),
+            (format!("a{ESC}[0mb😀"), vec!["a", "b😀"]),
+        ] {
+            let mut stripper = sample.as_str().non_esc();
+            let actual: Vec<&str> = stripper.by_ref().collect();
+            assert_eq!(expected, actual, "{sample:?}");
+            // Stays exhausted rather than re-emitting the last run.
+            assert_eq!(None, stripper.next());
+            assert_eq!(None, stripper.next());
+        }
+    }
+
+    #[test]
+    fn fs_escapes() {
+        for c in '`'..='~' {
+            standard_test(&format!("a{ESC}{c}b"), vec!["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn fp_and_fe_escapes() {
+        // Save/restore cursor, keypad modes, index, reverse index and next line.
+        for c in ['7', '8', '=', '>', 'D', 'M', 'E', 'H'] {
+            standard_test(&format!("a{ESC}{c}b"), vec!["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn nf_escapes() {
+        // Charset designation, 7/8-bit controls (S7C1T/S8C1T), DECALN, UTF-8 and 94^n sets.
+        for seq in ["(B", "(0", ")B", " F", " G", "#8", "%G", "$)A"] {
+            standard_test(&format!("a{ESC}{seq}b"), vec!["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn nf_malformed() {
+        standard_test(&format!("a{ESC}(\nb"), vec!["a", "\nb"]);
+        standard_test(&format!("a{ESC}({ESC}[0mb"), vec!["a", "b"]);
+        standard_test(&format!("a{ESC}( "), vec!["a"]);
+    }
+
+    #[test]
+    fn empty_osc() {
+        standard_test(&format!("a{ESC}]{BEL}b"), vec!["a", "b"]);
+        standard_test(&format!("a{ESC}]{ESC}{ST_CHAR}b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn nul_is_text() {
+        standard_test("a\0b", vec!["a\0b"]);
+        standard_test(&format!("{ESC}[0m\0x"), vec!["\0x"]);
+        standard_test(&format!("\0{ESC}[0m\0"), vec!["\0", "\0"]);
+    }
+
+    #[test]
+    fn nul_inside_string_sequences() {
+        standard_test(&format!("a{ESC}]0;t\0t{BEL}b"), vec!["a", "b"]);
+        standard_test(&format!("a{ESC}P\0{ESC}{ST_CHAR}b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn csi_private_modes_and_intermediates() {
+        standard_test("a\x1b[?25lb\x1b[?1049hc", vec!["a", "b", "c"]);
+        standard_test("a\x1b[>4;2md\x1b[=1u", vec!["a", "d"]);
+        // DECRQM and DECSCUSR, with `$` and space intermediates.
+        standard_test("a\x1b[?1$pb\x1b[2 qc", vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn csi_interrupted_by_control() {
+        standard_test("a\x1b[12\nb", vec!["a", "\nb"]);
+        standard_test("a\x1b[1\x07mb", vec!["a", "\x07mb"]);
+        standard_test("a\x1b[1;3\rb", vec!["a", "\rb"]);
+        standard_test("\x1b[3\n4m x", vec!["\n4m x"]);
+        standard_test("a\x1b[3\x004m x", vec!["a", "\x004m x"]);
+        standard_test("a\x1b[?\x07\x1b[0mb", vec!["a", "\x07", "b"]);
+        // A parameter byte can't follow an intermediate.
+        standard_test("a\x1b[ 1q", vec!["a", "1q"]);
+    }
+
+    #[test]
+    fn esc_aborts_csi() {
+        standard_test(&format!("{ESC}[12{ESC}[0mZ"), vec!["Z"]);
+        standard_test(&format!("a{ESC}[1;{ESC}]0;t{BEL}b"), vec!["a", "b"]);
+        standard_test(&format!("a{ESC}[1{ESC}éb"), vec!["a", "éb"]);
+    }
+
+    #[test]
+    fn c1_csi() {
+        standard_test("a\u{9b}0mb", vec!["a", "b"]);
+        standard_test("\u{9b}1;31mred\u{9b}0m", vec!["red"]);
+        standard_test("a\u{9b}12", vec!["a"]);
+    }
+
+    #[test]
+    fn c1_strings_end_with_c1_st() {
+        standard_test("a\u{9d}0;title\u{9c}b", vec!["a", "b"]);
+        standard_test("a\u{9d}0;title\x07b", vec!["a", "b"]);
+        standard_test(&format!("a\u{9d}0;title{ST}b"), vec!["a", "b"]);
+        standard_test("a\u{90}q#0;2\u{9c}b\u{9f}apc\u{9c}c", vec!["a", "b", "c"]);
+        standard_test(&format!("a{ESC}]0;title\u{9c}b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn c1_restarts_malformed_sequence() {
+        standard_test(&format!("a{ESC}\u{9b}1mb"), vec!["a", "b"]);
+        standard_test(&format!("a{ESC}(\u{9b}1mb"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn c1_aborts_csi() {
+        standard_test("\x1b[12\u{9b}0mX", vec!["X"]);
+        standard_test("a\x1b[1 \u{9d}0;t\x07b", vec!["a", "b"]);
+        // As 7-bit bytes, 0x9b is just a byte that doesn't belong in a CSI.
+        let stripped: Vec<_> = b"\x1b[12\x9b0mX".non_esc_bytes().collect();
+        assert_eq!(vec![&b"\x9b0mX"[..]], stripped);
+    }
+
+    #[test]
+    fn other_c1_is_text() {
+        standard_test("a\u{85}b\u{9c}c", vec!["a\u{85}b\u{9c}c"]);
+    }
+
+    #[test]
+    fn str_and_bytes_identical() {
+        let samples = [
+            format!("a{ESC}[1;31mb{ESC}[0m\tc"),
+            format!("{ESC}]0;title{BEL}x{ESC}]8;;u{ESC}{ST_CHAR}y"),
+            format!("{ESC}Pq#0{ESC}{ST_CHAR}{ESC}(Bz{ESC}7{ESC}{ESC}[m"),
+            format!("tail{ESC}[12"),
+        ];
+        for sample in &samples {
+            let from_str: Vec<&[u8]> = sample.as_str().non_esc().map(str::as_bytes).collect();
+            let from_bytes: Vec<&[u8]> =
+                AnsiByteStripper::with_options(sample.as_bytes(), Options::default()).collect();
+            assert_eq!(from_str, from_bytes, "{sample:?}");
+        }
+    }
+
+    #[test]
+    fn splits_only_where_bytes_removed() {
+        // `ESC z` is a whole escape; `ESC é` drops just the ESC. Either way the runs either
+        // side aren't adjacent in the source, so they can't be yielded as one slice.
+        standard_test(&format!("ab{ESC}zcd"), vec!["ab", "cd"]);
+        standard_test(&format!("ab{ESC}écd"), vec!["ab", "écd"]);
+        standard_test(&format!("ab{ESC}{ESC}{ESC}écd"), vec!["ab", "écd"]);
+    }
+
+    #[test]
+    fn reset_iterates_again() {
+        let sample = format!("Hello, {ESC}{CSI}0mworld{ESC}{CSI}123m!");
+        let mut stripper = sample.as_str().non_esc();
+        let first: Vec<&str> = stripper.by_ref().collect();
+        stripper.reset();
+        let second: Vec<&str> = stripper.collect();
+        assert_eq!(vec!["Hello, ", "world", "!"], first);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn reset_mid_iteration() {
+        let sample = format!("a{ESC}[mb");
+        let mut stripper = sample.as_str().non_esc();
+        assert_eq!(Some("a"), stripper.next());
+        stripper.reset();
+        assert_eq!(vec!["a", "b"], stripper.collect::<Vec<&str>>());
+    }
+
+    #[test]
+    fn spans() {
+        let spans: Vec<_> = "ab\x1b[0mcd".spans().collect();
+        assert_eq!(vec![(0..2, "ab"), (6..8, "cd")], spans);
+
+        let sample = "\x1b]0;t\x07é\u{9b}1mx\x1b[";
+        let spans: Vec<_> = sample.spans().collect();
+        assert_eq!(vec![(6..8, "é"), (12..13, "x")], spans);
+        assert!(spans
+            .iter()
+            .all(|(range, run)| &sample[range.clone()] == *run));
+        assert_eq!(0, "\x1b[0m".spans().count());
+    }
+
+    #[test]
+    fn esc_only() {
+        let seqs: Vec<_> = "a\x1b[31mb\x1b[0m".esc_only().collect();
+        assert_eq!(vec!["\x1b[31m", "\x1b[0m"], seqs);
+
+        let seqs: Vec<_> = "\x1b]0;t\x07x\u{9b}1m\x1b(Bdone\x1b[".esc_only().collect();
+        assert_eq!(vec!["\x1b]0;t\x07", "\u{9b}1m", "\x1b(B"], seqs);
+        assert_eq!(0, "plain".esc_only().count());
+    }
+
+    #[test]
+    fn segments() {
+        let segments: Vec<_> = "a\x1b[31mb\x1b\nc\x1b]0;".segments().collect();
+        assert_eq!(
+            vec![
+                Segment::Text("a"),
+                Segment::Escape("\x1b[31m"),
+                Segment::Text("b"),
+                Segment::Escape("\x1b"),
+                Segment::Text("\nc"),
+                Segment::Escape("\x1b]0;"),
+            ],
+            segments
+        );
+        assert_eq!(0, "".segments().count());
+    }
+
+    #[test]
+    fn kitty_graphics() {
+        let payload = "iVBORw0KGgo+/=".repeat(4096);
+        let image = format!("\x1b_Ga=T,f=100,i=31,m=0;{payload}\x1b\\");
+        let sample = format!("before{image}after");
+        standard_test(&sample, vec!["before", "after"]);
+        let sample = format!("a\x1b_Gq=2;{payload}\u{9c}b\u{9f}Ga=d,d=A\x1b\\c");
+        standard_test(&sample, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn visible_len() {
+        assert_eq!(2, "a\x1b[31m漢\x1b[0m".non_esc().visible_len());
+        assert_eq!(3, "\x1b]0;title\x07a\tb".non_esc().visible_len());
+        assert_eq!(0, "".non_esc().visible_len());
+    }
+}
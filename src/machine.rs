@@ -0,0 +1,166 @@
+//! The escape-sequence state machine, as a pure function of mode and char.
+
+use crate::{APC, BEL, CSI, DCS, ESC, OSC, PM, SOC, ST_CHAR};
+
+// The 8-bit C1 forms of the introducers and of ST.
+const C1_DCS: char = '\u{90}';
+const C1_SOS: char = '\u{98}';
+const C1_CSI: char = '\u{9b}';
+const C1_ST: char = '\u{9c}';
+const C1_OSC: char = '\u{9d}';
+const C1_PM: char = '\u{9e}';
+const C1_APC: char = '\u{9f}';
+
+/// The mode a C1 introducer goes straight into, as if it were `ESC` and its second char.
+fn c1_introducer(c: char) -> Option<Mode> {
+    match c {
+        C1_CSI => Some(Mode::InCsi),
+        C1_OSC => Some(Mode::InOsc),
+        C1_DCS | C1_SOS | C1_PM | C1_APC => Some(Mode::AwaitSt),
+        _ => None,
+    }
+}
+
+/// Current mode of the iterator.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Mode {
+    /// In visible text.
+    #[default]
+    Normal,
+    /// Just seen the ESC that starts a sequence.
+    InEsc,
+    /// In a DCS, SOS, PM or APC, waiting for ST.
+    AwaitSt,
+    /// In an OSC, waiting for BEL or ST.
+    InOsc,
+    /// In a CSI's parameter bytes, waiting for its final byte.
+    InCsi,
+    /// In a CSI's intermediate bytes, after which only more of them or the final byte may come.
+    InCsiIntermediate,
+    /// In an nF escape such as `ESC ( B`, waiting for its final byte.
+    InNf,
+    /// Seen an ESC inside an OSC, which may be the start of ST.
+    OscMaybeSt,
+    /// Seen an ESC while waiting for ST, which may be the start of it.
+    MaybeSt,
+    /// In a Linux console `ESC ] P nrrggbb`, counting the hex digits still to come.
+    InPalette(u8),
+}
+
+/// What to do with the char that caused a transition.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Action {
+    /// It's visible text. From inside a sequence, the sequence so far is malformed and dropped.
+    Keep,
+    /// It's part of the sequence in progress.
+    Drop,
+    /// It's the ESC or C1 introducer that starts a new sequence, abandoning any sequence in
+    /// progress.
+    StartSeq,
+    /// It's the last char of the sequence in progress.
+    EndSeq,
+}
+
+/// The mode after `c` in `mode`, and what `c` is.
+///
+/// The 8-bit C1 introducers (U+009B for CSI, U+009D for OSC, and so on) start sequences as
+/// `ESC` does, and U+009C ends strings as ST does; a caller that only wants 7-bit controls
+/// passes them as ordinary text instead. Options that change the machine, such as
+/// `linux_console`, are likewise applied by the caller on top of this.
+pub fn transition(mode: Mode, c: char) -> (Mode, Action) {
+    use Action::*;
+    match (mode, c) {
+        (Mode::Normal, ESC) => (Mode::InEsc, StartSeq),
+        (Mode::Normal, c) if c1_introducer(c).is_some() => (c1_introducer(c).unwrap(), StartSeq),
+        (Mode::Normal, _) => (Mode::Normal, Keep),
+
+        // For these we just await the ST (String Terminator)
+        (Mode::InEsc, DCS | SOC | PM | APC) => (Mode::AwaitSt, Drop),
+        // OSC (Operating System Command) has some special handling for BEL or ST
+        (Mode::InEsc, OSC) => (Mode::InOsc, Drop),
+        // Next is a CSI (Control Sequence Indicator)
+        (Mode::InEsc, CSI) => (Mode::InCsi, Drop),
+        // A stray ST with nothing to terminate: drop it whole.
+        (Mode::InEsc, ST_CHAR) => (Mode::Normal, EndSeq),
+        // nF: intermediate bytes, then a final byte, e.g. `ESC ( B`
+        (Mode::InEsc, ' '..='/') => (Mode::InNf, Drop),
+        // Fp, Fe and Fs: a complete two-byte escape, e.g. `ESC 7` or `ESC c`
+        (Mode::InEsc, '0'..='~') => (Mode::Normal, EndSeq),
+
+        (Mode::InNf, ' '..='/') => (Mode::InNf, Drop),
+        (Mode::InNf, '0'..='~') => (Mode::Normal, EndSeq),
+
+        // https://w.wiki/Bk2X#Control_Sequence_Introducer_commands
+        // Parameter bytes, including private prefixes such as `?` and `>`, then intermediate
+        // bytes, then the final byte. Anything else, even a newline, makes it malformed, and an
+        // ESC or C1 introducer starts a new sequence in its place.
+        (Mode::InCsi, '0'..='?') => (Mode::InCsi, Drop),
+        (Mode::InCsi | Mode::InCsiIntermediate, ' '..='/') => (Mode::InCsiIntermediate, Drop),
+        (Mode::InCsi | Mode::InCsiIntermediate, '@'..='~') => (Mode::Normal, EndSeq),
+
+        // BEL is magic end marker for OSC too.
+        (Mode::InOsc, BEL | C1_ST) => (Mode::Normal, EndSeq),
+        // Maybe about to get ST end?
+        (Mode::InOsc, ESC) => (Mode::OscMaybeSt, Drop),
+        (Mode::InOsc, _) => (Mode::InOsc, Drop),
+
+        (Mode::OscMaybeSt, ST_CHAR | BEL | C1_ST) => (Mode::Normal, EndSeq),
+        (Mode::OscMaybeSt, ESC) => (Mode::OscMaybeSt, Drop),
+        (Mode::OscMaybeSt, _) => (Mode::InOsc, Drop),
+
+        // Are we waiting on a String Termination (ST) char?
+        (Mode::AwaitSt, ESC) => (Mode::MaybeSt, Drop),
+        (Mode::AwaitSt, C1_ST) => (Mode::Normal, EndSeq),
+        (Mode::AwaitSt, _) => (Mode::AwaitSt, Drop),
+
+        (Mode::MaybeSt, ST_CHAR | C1_ST) => (Mode::Normal, EndSeq),
+        // An ESC in the data right before ST: this one may start it instead.
+        (Mode::MaybeSt, ESC) => (Mode::MaybeSt, Drop),
+        (Mode::MaybeSt, _) => (Mode::AwaitSt, Drop),
+
+        (Mode::InPalette(1), c) if c.is_ascii_hexdigit() => (Mode::Normal, EndSeq),
+        (Mode::InPalette(n), c) if c.is_ascii_hexdigit() => (Mode::InPalette(n - 1), Drop),
+
+        // Malformed: drop what we had and treat this char afresh.
+        (_, ESC) => (Mode::InEsc, StartSeq),
+        (_, c) if c1_introducer(c).is_some() => (c1_introducer(c).unwrap(), StartSeq),
+        _ => (Mode::Normal, Keep),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Action::*;
+    use Mode::*;
+
+    /// One char from each class the machine tells apart.
+    const CLASSES: [char; 14] = [
+        '\x1b', '\x07', '[', ']', 'P', '\\', ' ', '0', 'a', 'm', '\n', 'é', '\u{9b}', '\u{9c}',
+    ];
+
+    #[rustfmt::skip]
+    const TABLE: [(Mode, [(Mode, Action); 14]); 11] = [
+        //                   ESC                        BEL                        [                          ]                          P                          \                          ' '                        0                          a                          m                          \n                         é                          9B                         9C
+        (Normal,            [(InEsc, StartSeq),         (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+        (InEsc,             [(InEsc, StartSeq),         (Normal, Keep),            (InCsi, Drop),             (InOsc, Drop),             (AwaitSt, Drop),           (Normal, EndSeq),          (InNf, Drop),              (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+        (AwaitSt,           [(MaybeSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (Normal, EndSeq)]),
+        (InOsc,             [(OscMaybeSt, Drop),        (Normal, EndSeq),          (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (Normal, EndSeq)]),
+        (InCsi,             [(InEsc, StartSeq),         (Normal, Keep),            (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (InCsiIntermediate, Drop), (InCsi, Drop),             (Normal, EndSeq),          (Normal, EndSeq),          (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+        (InCsiIntermediate, [(InEsc, StartSeq),         (Normal, Keep),            (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (InCsiIntermediate, Drop), (Normal, Keep),            (Normal, EndSeq),          (Normal, EndSeq),          (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+        (InNf,              [(InEsc, StartSeq),         (Normal, Keep),            (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (InNf, Drop),              (Normal, EndSeq),          (Normal, EndSeq),          (Normal, EndSeq),          (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+        (OscMaybeSt,        [(OscMaybeSt, Drop),        (Normal, EndSeq),          (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (Normal, EndSeq),          (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (InOsc, Drop),             (Normal, EndSeq)]),
+        (MaybeSt,           [(MaybeSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (Normal, EndSeq),          (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (AwaitSt, Drop),           (Normal, EndSeq)]),
+        (InPalette(2),      [(InEsc, StartSeq),         (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (InPalette(1), Drop),      (InPalette(1), Drop),      (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+        (InPalette(1),      [(InEsc, StartSeq),         (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (Normal, EndSeq),          (Normal, EndSeq),          (Normal, Keep),            (Normal, Keep),            (Normal, Keep),            (InCsi, StartSeq),         (Normal, Keep)]),
+    ];
+
+    #[test]
+    fn every_mode_and_class() {
+        for (mode, row) in TABLE {
+            for (c, expected) in CLASSES.into_iter().zip(row) {
+                assert_eq!(expected, transition(mode, c), "{mode:?} on {c:?}");
+            }
+        }
+    }
+}
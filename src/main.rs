@@ -1,22 +1,326 @@
+use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::PathBuf;
+use std::process;
+use std::str;
 
-use ansi_strip::NonEsc;
+use ansi_strip::{parse_sgr, AnsiStripperBuilder, Segment, Segmented};
+use clap::{Parser, ValueEnum};
 
-fn main() {
-    let reader = io::stdin();
-    let mut writer = io::stdout();
+/// Strip ANSI escape codes from files or stdin, writing the plain text to stdout.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Print counts of what would be stripped instead of the stripped text.
+    #[arg(long)]
+    stats_only: bool,
+
+    /// Strip the input as one stream rather than line by line, so escape
+    /// sequences that span lines are removed whole.
+    #[arg(long)]
+    whole: bool,
+
+    /// Whether the output ends with a newline: `auto` if the input did.
+    #[arg(long, value_enum, default_value_t = TrailingNewline::Auto)]
+    trailing_newline: TrailingNewline,
+
+    /// Keep SGR (colour and style) sequences, stripping every other escape.
+    #[arg(short = 'c', long)]
+    keep_color: bool,
+
+    /// Also write each removed escape sequence to stderr, with its byte offset in the input.
+    #[arg(long)]
+    log_escapes: bool,
+
+    /// List the kinds of escape sequence recognised, and whether each would be stripped
+    /// with the other options given, instead of stripping anything.
+    #[arg(long)]
+    list_supported: bool,
+
+    /// Files to strip, in order; stdin if none.
+    files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TrailingNewline {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Why a run over one input stopped early.
+enum Failure {
+    /// The input couldn't be read; the other inputs are still stripped.
+    Read(io::Error),
+    /// Stdout couldn't be written, so there's no point going on.
+    Write(io::Error),
+}
+
+/// Feed `reader` to `strip` as records, each with its byte offset in the input and whether a
+/// newline ended it: the whole input with `whole`, otherwise each line.
+///
+/// Only the `\n` is taken off a record, so a `\r` before it and a last line without one come
+/// through unchanged. Records are raw bytes, which needn't be UTF-8.
+fn read_records(
+    mut reader: impl BufRead,
+    whole: bool,
+    strip: &mut impl FnMut(&[u8], usize, bool) -> io::Result<()>,
+) -> Result<(), Failure> {
+    if whole {
+        // One stream, so sequences that span lines are stripped as a unit.
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input).map_err(Failure::Read)?;
+        if !input.is_empty() {
+            match input.strip_suffix(b"\n") {
+                Some(record) => strip(record, 0, true),
+                None => strip(&input, 0, false),
+            }
+            .map_err(Failure::Write)?;
+        }
+        return Ok(());
+    }
+    let mut line = Vec::new();
+    let mut offset = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).map_err(Failure::Read)? == 0 {
+            return Ok(());
+        }
+        match line.strip_suffix(b"\n") {
+            Some(record) => strip(record, offset, true),
+            None => strip(&line, offset, false),
+        }
+        .map_err(Failure::Write)?;
+        offset += line.len();
+    }
+}
+
+/// A sample of each kind of sequence the stripper recognises.
+const KINDS: &[(&str, &str)] = &[
+    ("CSI, SGR (colour and style)", "\x1b[1;31m"),
+    ("CSI, other", "\x1b[2J"),
+    ("OSC, ended by BEL", "\x1b]0;title\x07"),
+    ("OSC, ended by ST", "\x1b]0;title\x1b\\"),
+    ("DCS, ended by ST", "\x1bPq#0\x1b\\"),
+    ("SOS, ended by ST", "\x1bXdata\x1b\\"),
+    ("PM, ended by ST", "\x1b^data\x1b\\"),
+    ("APC, ended by ST", "\x1b_data\x1b\\"),
+    ("nF, e.g. ESC ( B", "\x1b(B"),
+    ("Two-byte, e.g. ESC 7", "\x1b7"),
+    ("C1 introducers, e.g. U+009B", "\u{9b}2J"),
+];
+
+/// Write each kind of sequence and whether `builder` strips it, found by running it over
+/// the kind's sample.
+fn list_supported(builder: &AnsiStripperBuilder) -> io::Result<()> {
+    let mut writer = io::stdout().lock();
+    let width = KINDS.iter().map(|(kind, _)| kind.len()).max().unwrap_or(0);
+    for (kind, sample) in KINDS {
+        let status = match builder.strip(sample).as_str() {
+            "" => "stripped",
+            kept if kept == *sample => "kept",
+            _ => "not recognised",
+        };
+        writeln!(writer, "{kind:width$}  {status}")?;
+    }
+    Ok(())
+}
+
+/// Strip `record` with `builder`, returning the kept spans and how many escapes it had.
+///
+/// Valid UTF-8 is stripped as text, so that C1 introducers such as U+009B are recognised;
+/// anything else as raw bytes.
+fn strip_record<'a>(builder: &AnsiStripperBuilder, record: &'a [u8]) -> (Vec<&'a [u8]>, usize) {
+    match str::from_utf8(record) {
+        Ok(text) => {
+            let mut stripper = builder.build(text);
+            let kept = stripper.by_ref().map(str::as_bytes).collect();
+            (kept, stripper.escape_count())
+        }
+        Err(_) => {
+            let mut stripper = builder.from_bytes(record);
+            let kept = stripper.by_ref().collect();
+            (kept, stripper.escape_count())
+        }
+    }
+}
+
+/// Each escape in `record`, read the same way `strip_record` reads it.
+fn escapes_in(record: &[u8]) -> Vec<&[u8]> {
+    match str::from_utf8(record) {
+        Ok(text) => text
+            .segments()
+            .filter_map(|segment| match segment {
+                Segment::Escape(seq) => Some(seq.as_bytes()),
+                Segment::Text(_) => None,
+            })
+            .collect(),
+        Err(_) => AnsiStripperBuilder::new()
+            .byte_segments(record)
+            .filter_map(|segment| match segment {
+                Segment::Escape(seq) => Some(seq),
+                Segment::Text(_) => None,
+            })
+            .collect(),
+    }
+}
 
-    for input in reader.lines() {
-        if let Ok(line) = input {
-            writer
-                .write_all(line.as_str().non_esc().collect::<String>().as_bytes())
-                .unwrap();
-            writer.write(&[b'\n']).expect("Failed to write to stdout");
-        } else {
-            eprintln!("Error reading input");
-            break;
+/// `seq` with every byte outside printable ASCII, and `\`, written as `\xNN`.
+fn hex_escape(seq: &[u8]) -> String {
+    let mut out = String::with_capacity(seq.len() * 2);
+    for &b in seq {
+        match b {
+            b' '..=b'~' if b != b'\\' => out.push(char::from(b)),
+            _ => out.push_str(&format!("\\x{b:02x}")),
         }
     }
+    out
+}
+
+#[derive(Default)]
+struct Stats {
+    escapes: usize,
+    bytes_removed: usize,
+    lines_affected: usize,
 }
 
+impl Stats {
+    /// Tally one stripped record: how many escapes it had and which of its lines lost bytes.
+    fn record(&mut self, input: &[u8], kept: &[&[u8]], escapes: usize) {
+        let kept_len: usize = kept.iter().map(|s| s.len()).sum();
+        self.escapes += escapes;
+        self.bytes_removed += input.len() - kept_len;
+
+        // A line is untouched only if a single kept span covers all of it.
+        let base = input.as_ptr() as usize;
+        let mut kept = kept.iter().map(|s| {
+            let start = s.as_ptr() as usize - base;
+            start..start + s.len()
+        });
+        let mut span = kept.next();
+        let mut line_start = 0;
+        for line in input.split_inclusive(|&b| b == b'\n') {
+            let line_end = line_start + line.len();
+            while span.as_ref().is_some_and(|s| s.end < line_end) {
+                span = kept.next();
+            }
+            if !span
+                .as_ref()
+                .is_some_and(|s| s.start <= line_start && line_end <= s.end)
+            {
+                self.lines_affected += 1;
+            }
+            line_start = line_end;
+        }
+    }
+}
+
+/// Strip the inputs `args` names to stdout, returning whether any of them couldn't be read.
+///
+/// An error is from writing to stdout, which ends the run.
+fn run(args: Args) -> io::Result<bool> {
+    let Args {
+        stats_only,
+        whole,
+        trailing_newline,
+        keep_color,
+        log_escapes,
+        list_supported: list,
+        files,
+    } = args;
+    let builder = AnsiStripperBuilder::new().keep_sgr(keep_color);
+    if list {
+        list_supported(&builder)?;
+        return Ok(false);
+    }
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    let mut stats = Stats::default();
+    let mut records = 0;
+    // The last record's newline, held back until we know whether another record follows.
+    let mut pending_newline = false;
+
+    let mut strip = |record: &[u8], offset: usize, newline: bool| {
+        if log_escapes {
+            let mut log = io::stderr().lock();
+            for seq in escapes_in(record) {
+                let sgr = str::from_utf8(seq).ok().and_then(parse_sgr);
+                if keep_color && sgr.is_some() {
+                    continue;
+                }
+                let at = offset + (seq.as_ptr() as usize - record.as_ptr() as usize);
+                writeln!(log, "{at}: {}", hex_escape(seq)).expect("Failed to write to stderr");
+            }
+        }
+        let (kept, escapes) = strip_record(&builder, record);
+        if stats_only {
+            stats.record(record, &kept, escapes);
+            return Ok(());
+        }
+        if pending_newline {
+            writer.write_all(b"\n")?;
+        }
+        for s in kept {
+            writer.write_all(s)?;
+        }
+        records += 1;
+        pending_newline = newline;
+        Ok(())
+    };
+
+    // Like cat, an input that can't be read is reported and the rest are still stripped.
+    let mut failed = false;
+    // `None` is stdin, read only when there are no files.
+    let inputs: Vec<Option<&PathBuf>> = match files.is_empty() {
+        true => vec![None],
+        false => files.iter().map(Some).collect(),
+    };
+    for path in inputs {
+        let result = match path {
+            None => read_records(io::stdin().lock(), whole, &mut strip),
+            Some(path) => File::open(path)
+                .map_err(Failure::Read)
+                .and_then(|file| read_records(BufReader::new(file), whole, &mut strip)),
+        };
+        match result {
+            Ok(()) => {}
+            Err(Failure::Read(e)) => {
+                let name = path.map_or("stdin".into(), |path| path.display().to_string());
+                eprintln!("ansi-strip: {name}: {e}");
+                failed = true;
+            }
+            Err(Failure::Write(e)) => return Err(e),
+        }
+    }
+
+    let newline = match trailing_newline {
+        TrailingNewline::Auto => pending_newline,
+        TrailingNewline::Always => records > 0,
+        TrailingNewline::Never => false,
+    };
+    if newline {
+        writer.write_all(b"\n")?;
+    }
+
+    if stats_only {
+        writeln!(writer, "escapes: {}", stats.escapes)?;
+        writeln!(writer, "bytes removed: {}", stats.bytes_removed)?;
+        writeln!(writer, "lines affected: {}", stats.lines_affected)?;
+    }
+    writer.flush()?;
+    Ok(failed)
+}
+
+fn main() {
+    match run(Args::parse()) {
+        Ok(false) => {}
+        Ok(true) => process::exit(1),
+        // Whoever was reading has stopped, as with `| head`, so just stop too.
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("ansi-strip: stdout: {e}");
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,561 @@
+//! Parsing the parameters of SGR (Select Graphic Rendition, `ESC [ ... m`) sequences.
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use crate::kind::after_introducer;
+use crate::{AnsiStripper, NonEsc, Span, ESC};
+
+/// One SGR parameter.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SgrParam {
+    /// `0`, or an empty parameter
+    Reset,
+    /// `1`
+    Bold,
+    /// `2`, also known as faint
+    Dim,
+    /// `3`
+    Italic,
+    /// `4`
+    Underline,
+    /// `5`
+    Blink,
+    /// `7`
+    Reverse,
+    /// `8`
+    Conceal,
+    /// `9`
+    Strikethrough,
+    /// `22`, clears both bold and dim
+    NormalIntensity,
+    /// `23`
+    NotItalic,
+    /// `24`
+    NotUnderlined,
+    /// `25`
+    NotBlinking,
+    /// `27`
+    NotReversed,
+    /// `28`
+    Revealed,
+    /// `29`
+    NotStrikethrough,
+    /// Anything else, by its leading number. Extended colours (`38;5;n`, `38;2;r;g;b` and
+    /// the `48`/`58` equivalents) are a single `Unknown` with their arguments consumed.
+    Unknown(u16),
+}
+
+impl SgrParam {
+    fn from_code(code: u16) -> Self {
+        match code {
+            0 => Self::Reset,
+            1 => Self::Bold,
+            2 => Self::Dim,
+            3 => Self::Italic,
+            4 => Self::Underline,
+            5 => Self::Blink,
+            7 => Self::Reverse,
+            8 => Self::Conceal,
+            9 => Self::Strikethrough,
+            22 => Self::NormalIntensity,
+            23 => Self::NotItalic,
+            24 => Self::NotUnderlined,
+            25 => Self::NotBlinking,
+            27 => Self::NotReversed,
+            28 => Self::Revealed,
+            29 => Self::NotStrikethrough,
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+/// A foreground or background colour set by an SGR parameter.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SgrColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    /// `38;5;n`, one of the 256 indexed colours.
+    Indexed(u8),
+    /// `38;2;r;g;b`, a truecolour.
+    Rgb(u8, u8, u8),
+}
+
+impl SgrColor {
+    /// One of the 16 named colours, numbered as in `30`-`37` then `90`-`97`.
+    fn basic(n: u16) -> Self {
+        use SgrColor::*;
+        [
+            Black,
+            Red,
+            Green,
+            Yellow,
+            Blue,
+            Magenta,
+            Cyan,
+            White,
+            BrightBlack,
+            BrightRed,
+            BrightGreen,
+            BrightYellow,
+            BrightBlue,
+            BrightMagenta,
+            BrightCyan,
+            BrightWhite,
+        ][usize::from(n)]
+    }
+
+    /// The colour an extended `38`/`48`/`58` sets, from its `5;n` or `2;r;g;b` arguments.
+    fn extended(kind: Option<u16>, args: &[Option<u16>]) -> Option<Self> {
+        let byte = |arg: &Option<u16>| arg.and_then(|n| u8::try_from(n).ok());
+        match (kind?, args) {
+            (5, [n]) => Some(Self::Indexed(byte(n)?)),
+            (2, [r, g, b]) => Some(Self::Rgb(byte(r)?, byte(g)?, byte(b)?)),
+            _ => None,
+        }
+    }
+}
+
+/// The rendition in effect for a run of text, accumulated from the SGRs before it.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Sgr {
+    pub foreground: Option<SgrColor>,
+    pub background: Option<SgrColor>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub reverse: bool,
+    pub conceal: bool,
+    pub strikethrough: bool,
+}
+
+impl Sgr {
+    /// Apply the parameters of `seq`, returning false (and changing nothing) if it isn't an SGR.
+    pub fn apply(&mut self, seq: &str) -> bool {
+        let Some(params) = Params::of(seq) else {
+            return false;
+        };
+        let mut next = *self;
+        for item in params {
+            let Some((param, colour)) = item else {
+                return false;
+            };
+            match param {
+                SgrParam::Reset => next = Self::default(),
+                SgrParam::Bold => next.bold = true,
+                SgrParam::Dim => next.dim = true,
+                SgrParam::NormalIntensity => (next.bold, next.dim) = (false, false),
+                SgrParam::Italic => next.italic = true,
+                SgrParam::NotItalic => next.italic = false,
+                SgrParam::Underline => next.underline = true,
+                SgrParam::NotUnderlined => next.underline = false,
+                SgrParam::Blink => next.blink = true,
+                SgrParam::NotBlinking => next.blink = false,
+                SgrParam::Reverse => next.reverse = true,
+                SgrParam::NotReversed => next.reverse = false,
+                SgrParam::Conceal => next.conceal = true,
+                SgrParam::Revealed => next.conceal = false,
+                SgrParam::Strikethrough => next.strikethrough = true,
+                SgrParam::NotStrikethrough => next.strikethrough = false,
+                SgrParam::Unknown(30..=38 | 90..=97) if colour.is_some() => {
+                    next.foreground = colour
+                }
+                SgrParam::Unknown(39) => next.foreground = None,
+                SgrParam::Unknown(40..=48 | 100..=107) if colour.is_some() => {
+                    next.background = colour
+                }
+                SgrParam::Unknown(49) => next.background = None,
+                _ => {}
+            }
+        }
+        *self = next;
+        true
+    }
+}
+
+/// Split `s` into its runs of visible text, each with the `Sgr` in effect for it.
+///
+/// Escapes other than SGRs are stripped without touching the style.
+pub fn styled_segments(s: &str) -> StyledSegments<'_> {
+    StyledSegments {
+        stripper: s.non_esc(),
+        style: Sgr::default(),
+    }
+}
+
+/// At each iteration, returns the next run of visible text and its style.
+pub struct StyledSegments<'a> {
+    stripper: AnsiStripper<'a>,
+    style: Sgr,
+}
+
+impl<'a> Iterator for StyledSegments<'a> {
+    type Item = (&'a str, Sgr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stripper.next_span()? {
+                Span::Text(text) => return Some((text, self.style)),
+                Span::Escape(seq) => {
+                    self.style.apply(seq);
+                }
+            }
+        }
+    }
+}
+
+/// Strip `s`, also returning each distinct foreground and background colour its SGRs set, in
+/// the order they first appear.
+///
+/// A colour counts even if no text is shown in it. Underline colours (`58`) aren't included.
+#[cfg(feature = "alloc")]
+pub fn strip_with_palette(s: &str) -> (String, Vec<SgrColor>) {
+    let mut stripper = s.non_esc();
+    let mut text = String::with_capacity(s.len());
+    let mut palette = Vec::new();
+    while let Some(span) = stripper.next_span() {
+        let seq = match span {
+            Span::Text(run) => {
+                text.push_str(run);
+                continue;
+            }
+            Span::Escape(seq) => seq,
+        };
+        for (param, colour) in Params::of(seq).into_iter().flatten().flatten() {
+            if let (SgrParam::Unknown(30..=38 | 40..=48 | 90..=97 | 100..=107), Some(colour)) =
+                (param, colour)
+            {
+                if !palette.contains(&colour) {
+                    palette.push(colour);
+                }
+            }
+        }
+    }
+    (text, palette)
+}
+
+/// True if `seq` is a complete SGR sequence: `esc [` or U+009B, parameters, `m`.
+///
+/// A private prefix such as `ESC [ > 4 ; 2 m` (xterm's modifyOtherKeys) also ends in `m` but
+/// sets a terminal resource rather than the rendition, so it isn't an SGR.
+pub(crate) fn is_sgr(seq: &[u8], esc: u8) -> bool {
+    sgr_params(seq, esc).is_some()
+}
+
+/// True if `seq` is an SGR that only resets, e.g. `ESC [ m` or `ESC [ 0 m`.
+pub(crate) fn is_sgr_reset(seq: &[u8], esc: u8) -> bool {
+    sgr_params(seq, esc).is_some_and(|p| p.iter().all(|&b| b == b'0' || b == b';'))
+}
+
+/// The parameter bytes between the CSI introducer and the final `m`, if `seq` is an SGR.
+fn sgr_params(seq: &[u8], esc: u8) -> Option<&[u8]> {
+    let params = after_introducer(seq, esc, b'[', 0x9b)?.strip_suffix(b"m")?;
+    params
+        .iter()
+        .all(|&b| b.is_ascii_digit() || b == b';' || b == b':')
+        .then_some(params)
+}
+
+/// The parameters of an SGR sequence, or `None` if `seq` isn't one.
+///
+/// Colon sub-parameters (e.g. `4:3`) are reduced to their leading number. A private prefix
+/// like `ESC [ > 4 ; 2 m` gives `None`, as it isn't an SGR.
+#[cfg(feature = "alloc")]
+pub fn parse_sgr(seq: &str) -> Option<Vec<SgrParam>> {
+    Params::of(seq)?
+        .map(|item| item.map(|(param, _)| param))
+        .collect()
+}
+
+/// The parameters of an SGR, each with the colour it sets, if any; `None` for one that isn't
+/// a number.
+struct Params<'a> {
+    fields: core::str::Split<'a, char>,
+}
+
+impl<'a> Params<'a> {
+    fn of(seq: &'a str) -> Option<Self> {
+        // Only ASCII passed the check, so this is still a valid str.
+        let params = core::str::from_utf8(sgr_params(seq.as_bytes(), ESC as u8)?).ok()?;
+        Some(Self {
+            fields: params.split(';'),
+        })
+    }
+}
+
+fn number(p: &str) -> Option<u16> {
+    if p.is_empty() {
+        Some(0)
+    } else {
+        p.parse().ok()
+    }
+}
+
+impl Iterator for Params<'_> {
+    type Item = Option<(SgrParam, Option<SgrColor>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let field = self.fields.next()?;
+        let mut subs = field.split(':');
+        let Some(code) = number(subs.next().unwrap_or_default()) else {
+            return Some(None);
+        };
+        let colour = match code {
+            30..=37 => Some(SgrColor::basic(code - 30)),
+            40..=47 => Some(SgrColor::basic(code - 40)),
+            90..=97 => Some(SgrColor::basic(code - 90 + 8)),
+            100..=107 => Some(SgrColor::basic(code - 100 + 8)),
+            38 | 48 | 58 if field.contains(':') => {
+                // `38:5:n`, or `38:2:r:g:b` with an optional colour space id before `r`.
+                let kind = subs.next().map(number).unwrap_or_default();
+                let mut args = [None; 4];
+                let mut n = 0;
+                for sub in subs {
+                    if n == args.len() {
+                        n += 1;
+                        break;
+                    }
+                    args[n] = number(sub);
+                    n += 1;
+                }
+                match (kind, n) {
+                    (Some(2), 4) => SgrColor::extended(kind, &args[1..]),
+                    (_, 0..=4) => SgrColor::extended(kind, &args[..n]),
+                    _ => None,
+                }
+            }
+            38 | 48 | 58 => {
+                // Extended colour: `5;n` or `2;r;g;b` follow.
+                let kind = self.fields.next().map(number).unwrap_or_default();
+                let count = match kind {
+                    Some(5) => 1,
+                    Some(2) => 3,
+                    _ => 0,
+                };
+                let mut args = [None; 3];
+                let mut n = 0;
+                for field in self.fields.by_ref().take(count) {
+                    args[n] = number(field);
+                    n += 1;
+                }
+                SgrColor::extended(kind, &args[..n])
+            }
+            _ => None,
+        };
+        Some(Some((SgrParam::from_code(code), colour)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use SgrParam::*;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn attributes() {
+        assert_eq!(
+            Some(vec![Dim, Italic, Strikethrough]),
+            parse_sgr("\x1b[2;3;9m")
+        );
+        assert_eq!(
+            Some(vec![Bold, Underline, Blink, Reverse, Conceal]),
+            parse_sgr("\x1b[1;4;5;7;8m")
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn attribute_resets() {
+        assert_eq!(
+            Some(vec![
+                NormalIntensity,
+                NotItalic,
+                NotUnderlined,
+                NotBlinking,
+                NotReversed,
+                Revealed,
+                NotStrikethrough
+            ]),
+            parse_sgr("\x1b[22;23;24;25;27;28;29m")
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn empty_params_reset() {
+        assert_eq!(Some(vec![Reset]), parse_sgr("\x1b[m"));
+        assert_eq!(Some(vec![Bold, Reset, Italic]), parse_sgr("\x1b[1;;3m"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn extended_colours_consume_arguments() {
+        assert_eq!(Some(vec![Unknown(38), Dim]), parse_sgr("\x1b[38;5;5;2m"));
+        assert_eq!(
+            Some(vec![Unknown(48), Italic]),
+            parse_sgr("\x1b[48;2;1;2;3;3m")
+        );
+        assert_eq!(Some(vec![Underline]), parse_sgr("\x1b[4:3m"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn not_sgr() {
+        assert_eq!(None, parse_sgr("\x1b[2J"));
+        assert_eq!(None, parse_sgr("\x1b[?25h"));
+        assert_eq!(None, parse_sgr("\x1b]0;m\x07"));
+        assert_eq!(None, parse_sgr("plain m"));
+        assert_eq!(None, parse_sgr("\x1b[>4;2m"));
+        assert_eq!(None, parse_sgr("\x1b[=1m"));
+        assert_eq!(None, parse_sgr("\x1b[?1m"));
+    }
+
+    fn style_after(seqs: &[&str]) -> Sgr {
+        let mut style = Sgr::default();
+        for seq in seqs {
+            assert!(style.apply(seq), "{seq:?}");
+        }
+        style
+    }
+
+    #[test]
+    fn style_tracks_set_and_clear() {
+        let default = Sgr::default();
+        assert_eq!(default, style_after(&[]));
+        assert_ne!(default, style_after(&["\x1b[1m"]));
+        assert_eq!(default, style_after(&["\x1b[1;2m", "\x1b[22m"]));
+        assert_eq!(
+            default,
+            style_after(&["\x1b[31;44m", "\x1b[39m", "\x1b[49m"])
+        );
+        assert_ne!(
+            default,
+            style_after(&["\x1b[38;5;1m", "\x1b[4m", "\x1b[39m"])
+        );
+        assert_eq!(default, style_after(&["\x1b[3;7;38;2;1;2;3m", "\x1b[m"]));
+        assert_eq!(default, style_after(&["\x1b[53m"]));
+        let all = style_after(&["\x1b[2;5;7;8;9m"]);
+        assert!(all.dim && all.blink && all.reverse && all.conceal && all.strikethrough);
+        assert_eq!(
+            default,
+            style_after(&["\x1b[2;5;7;8;9m", "\x1b[22;25;27;28;29m"])
+        );
+    }
+
+    #[test]
+    fn truecolour_segments() {
+        let segments: Vec<_> =
+            styled_segments("\x1b[38;2;255;128;0;48;5;17mhot\x1b[0m cold").collect();
+        let hot = Sgr {
+            foreground: Some(SgrColor::Rgb(255, 128, 0)),
+            background: Some(SgrColor::Indexed(17)),
+            ..Sgr::default()
+        };
+        assert_eq!(vec![("hot", hot), (" cold", Sgr::default())], segments);
+    }
+
+    #[test]
+    fn c1_csi_styles() {
+        let segments: Vec<_> = styled_segments("\u{9b}1;32mok\u{9b}0m done").collect();
+        let bold_green = Sgr {
+            bold: true,
+            foreground: Some(SgrColor::Green),
+            ..Sgr::default()
+        };
+        assert_eq!(
+            vec![("ok", bold_green), (" done", Sgr::default())],
+            segments
+        );
+        assert!(is_sgr(b"\x9b0m", b'\x1b'));
+    }
+
+    #[test]
+    fn style_accumulates_across_sgrs() {
+        let segments: Vec<_> =
+            styled_segments("\x1b[1ma\x1b[3;31mb\x1b[2Jc\x1b[22;4;39md\x1b[me").collect();
+        let bold = Sgr {
+            bold: true,
+            ..Sgr::default()
+        };
+        let bold_red = Sgr {
+            italic: true,
+            foreground: Some(SgrColor::Red),
+            ..bold
+        };
+        let underlined = Sgr {
+            bold: false,
+            underline: true,
+            foreground: None,
+            ..bold_red
+        };
+        assert_eq!(
+            vec![
+                ("a", bold),
+                ("b", bold_red),
+                ("c", bold_red),
+                ("d", underlined),
+                ("e", Sgr::default())
+            ],
+            segments
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn palette_in_first_seen_order() {
+        let (text, palette) = strip_with_palette(
+            "\x1b[31merror\x1b[0m \x1b[32mok\x1b[0m \x1b[1;31magain\x1b[0m\x1b[2J",
+        );
+        assert_eq!("error ok again", text);
+        assert_eq!(vec![SgrColor::Red, SgrColor::Green], palette);
+        let c1 = strip_with_palette("\u{9b}34mblue\u{9b}0m");
+        assert_eq!(("blue".to_string(), vec![SgrColor::Blue]), c1);
+
+        let (_, palette) =
+            strip_with_palette("\x1b[38;5;208;41ma\x1b[58;5;1;101mb\x1b[38;2;1;2;3mc\x1b[31m");
+        assert_eq!(
+            vec![
+                SgrColor::Indexed(208),
+                SgrColor::Red,
+                SgrColor::BrightRed,
+                SgrColor::Rgb(1, 2, 3)
+            ],
+            palette
+        );
+        assert_eq!(("plain".into(), vec![]), strip_with_palette("plain"));
+    }
+
+    #[test]
+    fn colour_forms() {
+        let fg = |seq: &str| {
+            let mut sgr = Sgr::default();
+            assert!(sgr.apply(seq));
+            sgr.foreground
+        };
+        assert_eq!(Some(SgrColor::BrightCyan), fg("\x1b[96m"));
+        assert_eq!(Some(SgrColor::Indexed(208)), fg("\x1b[38:5:208m"));
+        assert_eq!(Some(SgrColor::Rgb(1, 2, 3)), fg("\x1b[38:2::1:2:3m"));
+        assert_eq!(Some(SgrColor::Rgb(1, 2, 3)), fg("\x1b[38:2:1:2:3m"));
+        assert_eq!(None, fg("\x1b[38;5;300m"));
+        assert_eq!(Some(SgrColor::Blue), fg("\x1b[34;48;5;1m"));
+        assert!(!Sgr::default().apply("\x1b[2J"));
+    }
+}
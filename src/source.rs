@@ -0,0 +1,153 @@
+use core::iter::{Copied, Enumerate, Map};
+use core::ops::Range;
+use core::slice::Iter;
+use core::str::{Bytes, CharIndices};
+
+use memchr::{memchr2, memchr3};
+
+use crate::ControlMode;
+
+/// Input an `AnsiStripper` can run over: string slices, or byte slices that may not be UTF-8.
+///
+/// The state machine sees the input as a series of units, each with its byte offset and its
+/// value as a `char`. A `&str` unit is a `char`; a `&[u8]` unit is a single byte, widened
+/// to the `char` with the same value.
+pub trait EscSource<'a>: Copy {
+    type Units: Iterator<Item = (usize, char)>;
+
+    /// Whether C1 controls introduce sequences when the builder doesn't say.
+    const CONTROL_MODE: ControlMode;
+
+    /// Each unit along with its byte offset.
+    fn units(self) -> Self::Units;
+
+    /// `units`, for input already known to be all ASCII, so that every unit is one byte and
+    /// nothing needs decoding.
+    fn ascii_units(self) -> Self::Units {
+        self.units()
+    }
+
+    /// The number of bytes the unit `c` takes up.
+    fn unit_len(c: char) -> usize;
+
+    /// The length of the run at the start of `bytes` with none of the `stops` bytes and, with
+    /// `eight_bit`, nothing that could be a C1 control.
+    ///
+    /// The run may stop short at a byte that turns out to be plain text; it must never run
+    /// past one that isn't.
+    fn plain_len(bytes: &[u8], stops: [u8; 2], eight_bit: bool) -> usize;
+
+    /// The sub-slice covering the byte offsets `range`.
+    fn slice(self, range: Range<usize>) -> Self;
+
+    fn as_bytes(self) -> &'a [u8];
+
+    fn from_static(s: &'static str) -> Self;
+}
+
+/// The units of a `&str`: its chars, or just its bytes when it's known to be ASCII.
+#[derive(Clone, Debug)]
+pub enum StrUnits<'a> {
+    Chars(CharIndices<'a>),
+    Ascii(Enumerate<Bytes<'a>>),
+}
+
+impl Iterator for StrUnits<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Chars(chars) => chars.next(),
+            Self::Ascii(bytes) => bytes.next().map(|(i, b)| (i, char::from(b))),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Chars(chars) => chars.size_hint(),
+            Self::Ascii(bytes) => bytes.size_hint(),
+        }
+    }
+}
+
+impl<'a> EscSource<'a> for &'a str {
+    type Units = StrUnits<'a>;
+
+    // A C1 char in a `str` can't be anything else.
+    const CONTROL_MODE: ControlMode = ControlMode::EightBit;
+
+    fn units(self) -> Self::Units {
+        StrUnits::Chars(self.char_indices())
+    }
+
+    fn ascii_units(self) -> Self::Units {
+        StrUnits::Ascii(self.bytes().enumerate())
+    }
+
+    fn unit_len(c: char) -> usize {
+        c.len_utf8()
+    }
+
+    fn plain_len(bytes: &[u8], [a, b]: [u8; 2], eight_bit: bool) -> usize {
+        // Every C1 char, U+0080 to U+009F, is encoded as 0xc2 and a second byte.
+        let end = match eight_bit {
+            true => memchr3(a, b, 0xc2, bytes),
+            false => memchr2(a, b, bytes),
+        };
+        end.unwrap_or(bytes.len())
+    }
+
+    fn slice(self, range: Range<usize>) -> Self {
+        &self[range]
+    }
+
+    fn as_bytes(self) -> &'a [u8] {
+        str::as_bytes(self)
+    }
+
+    fn from_static(s: &'static str) -> Self {
+        s
+    }
+}
+
+type ByteUnits<'a> = Map<Enumerate<Copied<Iter<'a, u8>>>, fn((usize, u8)) -> (usize, char)>;
+
+impl<'a> EscSource<'a> for &'a [u8] {
+    type Units = ByteUnits<'a>;
+
+    // Bytes 0x80 to 0x9f are continuation bytes in UTF-8, so only ESC is safe to assume.
+    const CONTROL_MODE: ControlMode = ControlMode::SevenBit;
+
+    fn units(self) -> Self::Units {
+        self.iter()
+            .copied()
+            .enumerate()
+            .map(|(i, b)| (i, char::from(b)))
+    }
+
+    fn unit_len(_: char) -> usize {
+        1
+    }
+
+    fn plain_len(bytes: &[u8], [a, b]: [u8; 2], eight_bit: bool) -> usize {
+        let end = match eight_bit {
+            true => bytes
+                .iter()
+                .position(|&c| c == a || c == b || (0x80..=0x9f).contains(&c)),
+            false => memchr2(a, b, bytes),
+        };
+        end.unwrap_or(bytes.len())
+    }
+
+    fn slice(self, range: Range<usize>) -> Self {
+        &self[range]
+    }
+
+    fn as_bytes(self) -> &'a [u8] {
+        self
+    }
+
+    fn from_static(s: &'static str) -> Self {
+        s.as_bytes()
+    }
+}
@@ -0,0 +1,479 @@
+use core::{fmt, mem};
+
+use alloc::{boxed::Box, string::String, string::ToString};
+
+use crate::builder::Options;
+use crate::kind::parse_clipboard;
+use crate::{parse_osc, AnsiStripper, Mode, NonEscBytes, Span};
+
+const OSC_CLIPBOARD: u32 = 52;
+
+type OscCallback<'f> = Box<dyn FnMut(u32, &str) + 'f>;
+type ClipboardCallback<'f> = Box<dyn FnMut(&str, &[u8]) + 'f>;
+
+/// What one `push_counted` stripped.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct PushStats {
+    /// Sequences removed, counting one split across pushes in the push that completes it.
+    pub sequences_removed: usize,
+    /// The total length of those sequences, including any part from earlier pushes.
+    pub bytes_removed: usize,
+}
+
+/// Strips input that arrives in pieces, carrying incomplete escape sequences between pushes.
+///
+/// Visible text is returned as soon as it's seen; an escape sequence cut off at the end of
+/// one chunk is held back until a later chunk completes it.
+#[derive(Default)]
+pub struct StatefulStripper<'f> {
+    options: Options,
+    mode: Mode,
+    // The unterminated sequence so far, from its introducer on.
+    pending: String,
+    column: usize,
+    on_osc: Option<OscCallback<'f>>,
+    on_clipboard: Option<ClipboardCallback<'f>>,
+}
+
+impl<'f> StatefulStripper<'f> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn with_options(mut options: Options) -> Self {
+        options.lenient = false;
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Resume from state saved with `into_parts`.
+    pub fn from_parts(mode: Mode, pending: String) -> Self {
+        Self {
+            mode,
+            pending,
+            ..Self::default()
+        }
+    }
+
+    /// Give up the parser mode and any pending partial sequence, e.g. to checkpoint them.
+    ///
+    /// Options and callbacks aren't part of the state and are dropped.
+    pub fn into_parts(self) -> (Mode, String) {
+        (self.mode, self.pending)
+    }
+
+    /// Call `f` with the command number and payload of each OSC as it's stripped.
+    pub fn on_osc(mut self, f: impl FnMut(u32, &str) + 'f) -> Self {
+        self.on_osc = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with the selection (`c`, `p`, etc.) and decoded data of each OSC 52 stripped.
+    ///
+    /// Only called when the stripper was built with `osc52_extract(true)`.
+    pub fn on_clipboard(mut self, f: impl FnMut(&str, &[u8]) + 'f) -> Self {
+        self.on_clipboard = Some(Box::new(f));
+        self
+    }
+
+    /// Strip the next chunk of input, returning the visible text it completes.
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.push_counted(chunk).0
+    }
+
+    /// `push`, also returning what was stripped, e.g. to tie removed sequences to a
+    /// timestamped event in a recording.
+    pub fn push_counted(&mut self, chunk: &str) -> (String, PushStats) {
+        // The state machine carries on from the saved mode, so each chunk is only run over
+        // once. A pending sequence of two bytes or less is replayed instead: the Linux console
+        // palette needs to see the `ESC ]` it starts with, and replaying that costs nothing.
+        let replayed;
+        let resume = self.mode != Mode::Normal && self.pending.len() > 2;
+        let src = if resume {
+            chunk
+        } else {
+            replayed = mem::take(&mut self.pending) + chunk;
+            replayed.as_str()
+        };
+
+        let mut stripper = AnsiStripper::with_options(src, mem::take(&mut self.options));
+        if resume {
+            stripper.resume_in(self.mode);
+        }
+        stripper.column = self.column;
+        let mut out = String::new();
+        let mut stats = PushStats::default();
+        while let Some(span) = stripper.next_span() {
+            match span {
+                Span::Text(s) => out.push_str(&stripper.options.map_text(s)),
+                Span::Escape(seq) => {
+                    // Only the end of a sequence begun in an earlier chunk is in this one.
+                    let joined;
+                    let seq = if stripper.continued {
+                        joined = mem::take(&mut self.pending) + seq;
+                        joined.as_str()
+                    } else {
+                        seq
+                    };
+                    if let Some((command, payload)) = parse_osc(seq) {
+                        if let Some(f) = &mut self.on_osc {
+                            f(command, payload);
+                        }
+                        if let (true, Some(f), Some((selection, data))) = (
+                            command == OSC_CLIPBOARD && stripper.options.osc52_extract,
+                            &mut self.on_clipboard,
+                            parse_clipboard(payload),
+                        ) {
+                            f(selection, &data);
+                        }
+                    }
+                    if stripper.options.keeps(seq.as_bytes()) {
+                        out.push_str(seq);
+                    } else {
+                        stats.sequences_removed += 1;
+                        stats.bytes_removed += seq.len();
+                        if let Some(uri) = stripper.options.preserved_uri(seq.as_bytes()) {
+                            out.push_str(&seq[uri]);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.column = stripper.column;
+        self.options = stripper.options;
+        self.mode = Mode::Normal;
+        match stripper
+            .unterminated
+            .filter(|_| !self.options.truncate_on_unterminated)
+        {
+            Some((_, mode)) if stripper.continued => {
+                self.mode = mode;
+                self.pending.push_str(src);
+            }
+            Some((start, mode)) => {
+                self.mode = mode;
+                self.pending = src[start..].to_string();
+            }
+            None => self.pending.clear(),
+        }
+        (out, stats)
+    }
+}
+
+impl fmt::Debug for StatefulStripper<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatefulStripper")
+            .field("options", &self.options)
+            .field("mode", &self.mode)
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Strips a byte stream that arrives in chunks, for callers that drive it themselves rather
+/// than through an iterator or `std::io`.
+///
+/// Visible bytes go straight to the callback. A sequence cut off at the end of a chunk needn't
+/// be kept, as nothing of it is output: only the mode it left the state machine in is, and
+/// the next chunk carries on from there, so each byte is looked at once.
+#[derive(Clone, Debug, Default)]
+pub struct StripState {
+    mode: Mode,
+}
+
+impl StripState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Strip `chunk` as the continuation of the stream, calling `out` with each visible run.
+    pub fn feed(&mut self, chunk: &[u8], out: &mut impl FnMut(&[u8])) {
+        let mut stripper = chunk.non_esc_bytes();
+        stripper.resume_in(self.mode);
+        for run in stripper.by_ref() {
+            out(run);
+        }
+        self.mode = stripper.unterminated.map_or(Mode::Normal, |(_, mode)| mode);
+    }
+
+    /// End the stream, calling `out` with any visible text still to come and dropping any
+    /// sequence still incomplete, as the iterators drop one at the end of their input. The
+    /// state is left ready for a new stream.
+    pub fn finish(&mut self, out: &mut impl FnMut(&[u8])) {
+        // Visible runs are passed on as `feed` finds them, so none is waiting here.
+        let _ = out;
+        self.mode = Mode::Normal;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnsiStripperBuilder;
+
+    #[test]
+    fn plain_chunks() {
+        let mut stripper = StatefulStripper::new();
+        assert_eq!("ab", stripper.push("ab"));
+        assert_eq!("cd", stripper.push("cd"));
+    }
+
+    #[test]
+    fn sequence_split_across_chunks() {
+        let mut stripper = StatefulStripper::new();
+        assert_eq!("a", stripper.push("a\x1b"));
+        assert_eq!("", stripper.push("[3"));
+        assert_eq!("b", stripper.push("1mb"));
+    }
+
+    #[test]
+    fn csi_aborted_at_chunk_end() {
+        let mut stripper = StatefulStripper::new();
+        assert_eq!("", stripper.push("\x1b[12"));
+        assert_eq!(Mode::InCsi, stripper.mode);
+        // The ESC aborts the CSI, so only it is carried over.
+        assert_eq!("", stripper.push("\x1b"));
+        assert_eq!(
+            (Mode::InEsc, "\x1b"),
+            (stripper.mode, stripper.pending.as_str())
+        );
+        assert_eq!("Z", stripper.push("[0mZ"));
+    }
+
+    #[test]
+    fn push_stats() {
+        let mut stripper = StatefulStripper::new();
+        let stats = PushStats {
+            sequences_removed: 2,
+            bytes_removed: 9,
+        };
+        assert_eq!(
+            ("red".to_string(), stats),
+            stripper.push_counted("\x1b[31mred\x1b[0m")
+        );
+        assert_eq!(
+            ("a".to_string(), PushStats::default()),
+            stripper.push_counted("a\x1b]0;")
+        );
+        let stats = PushStats {
+            sequences_removed: 1,
+            bytes_removed: 6,
+        };
+        assert_eq!(("b".to_string(), stats), stripper.push_counted("t\x07b"));
+    }
+
+    #[test]
+    fn push_stats_skip_kept() {
+        let mut stripper = AnsiStripperBuilder::new().keep_sgr(true).stateful();
+        let (out, stats) = stripper.push_counted("\x1b[1mx\x1b[2J");
+        assert_eq!("\x1b[1mx", out);
+        assert_eq!((1, 4), (stats.sequences_removed, stats.bytes_removed));
+    }
+
+    #[test]
+    fn one_char_at_a_time() {
+        let sample = "x\x1b[1mbold\x1b]0;title\x1b\\\x1bPq\x1b\\y";
+        let mut stripper = StatefulStripper::new();
+        let mut actual = String::new();
+        for (i, c) in sample.char_indices() {
+            actual.push_str(&stripper.push(&sample[i..i + c.len_utf8()]));
+        }
+        assert_eq!("xboldy", actual);
+    }
+
+    #[test]
+    fn parts_mid_escape() {
+        let mut stripper = StatefulStripper::new();
+        assert_eq!("a", stripper.push("a\x1b]0;ti"));
+        let (mode, pending) = stripper.into_parts();
+        assert_eq!(Mode::InOsc, mode);
+        assert_eq!("\x1b]0;ti", pending);
+    }
+
+    #[test]
+    fn parts_in_normal_mode() {
+        let mut stripper = StatefulStripper::new();
+        stripper.push("a\x1b[0mb");
+        assert_eq!((Mode::Normal, String::new()), stripper.into_parts());
+    }
+
+    #[test]
+    fn checkpoint_and_resume() {
+        let chunks = [
+            "x\x1b[",
+            "1;3",
+            "1mred\x1b",
+            "]0;title",
+            "\x1b",
+            "\\y\x1bP",
+            "data\x1b\\z",
+        ];
+        let mut uninterrupted = StatefulStripper::new();
+        let expected: String = chunks.iter().map(|c| uninterrupted.push(c)).collect();
+        assert_eq!("xredyz", expected);
+
+        for split in 1..chunks.len() {
+            let mut stripper = StatefulStripper::new();
+            let mut actual: String = chunks[..split].iter().map(|c| stripper.push(c)).collect();
+            let (mode, pending) = stripper.into_parts();
+            let mut resumed = StatefulStripper::from_parts(mode, pending);
+            actual.extend(chunks[split..].iter().map(|c| resumed.push(c)));
+            assert_eq!(expected, actual, "checkpoint after chunk {split}");
+        }
+    }
+
+    #[test]
+    fn osc_side_channel() {
+        let mut notes = vec![];
+        let mut stripper =
+            StatefulStripper::new().on_osc(|n, payload| notes.push((n, payload.to_string())));
+        let out = stripper.push("build \x1b]9;Build finished\x07done\x1b]0;title\x1b\\\n");
+        drop(stripper);
+        assert_eq!("build done\n", out);
+        assert_eq!(
+            vec![(9, "Build finished".to_string()), (0, "title".to_string())],
+            notes
+        );
+    }
+
+    #[test]
+    fn long_sequence_in_small_chunks() {
+        let mut notes = vec![];
+        let mut stripper = AnsiStripperBuilder::new()
+            .keep_exact(&["\x1b[1m"])
+            .stateful()
+            .on_osc(|n, payload| notes.push((n, payload.len())));
+        let title = "t".repeat(300_000);
+        let stream = format!("a\x1b]2;{title}\x07b\x1b[1mc\x1bP{title}\x1b\\d\x1b[3");
+        let mut out = String::new();
+        let mut removed = PushStats::default();
+        for chunk in stream.as_bytes().chunks(5) {
+            let (text, stats) = stripper.push_counted(core::str::from_utf8(chunk).unwrap());
+            out.push_str(&text);
+            removed.sequences_removed += stats.sequences_removed;
+            removed.bytes_removed += stats.bytes_removed;
+        }
+        assert_eq!((Mode::InCsi, "\x1b[3".to_string()), stripper.into_parts());
+        assert_eq!("ab\x1b[1mcd", out);
+        assert_eq!(2, removed.sequences_removed);
+        assert_eq!(2 * title.len() + 9, removed.bytes_removed);
+        assert_eq!(vec![(2, title.len())], notes);
+    }
+
+    #[test]
+    fn linux_palette_split_after_introducer() {
+        let mut stripper = AnsiStripperBuilder::new().linux_console(true).stateful();
+        assert_eq!("a", stripper.push("a\x1b]"));
+        assert_eq!("", stripper.push("P0"));
+        assert_eq!("bc", stripper.push("123456b\x1b]Rc"));
+    }
+
+    #[test]
+    fn osc_c1_forms() {
+        let mut notes = vec![];
+        let mut stripper = AnsiStripperBuilder::new()
+            .stateful()
+            .on_osc(|n, payload| notes.push((n, payload.to_string())));
+        assert_eq!("ab", stripper.push("a\u{9d}2;title\u{9c}b"));
+        assert_eq!("c", stripper.push("\x1b]9;done\u{9c}c"));
+        drop(stripper);
+        assert_eq!(
+            vec![(2, "title".to_string()), (9, "done".to_string())],
+            notes
+        );
+    }
+
+    #[test]
+    fn osc_split_across_chunks() {
+        let mut notes = vec![];
+        let mut stripper =
+            StatefulStripper::new().on_osc(|n, payload| notes.push((n, payload.to_string())));
+        assert_eq!("a", stripper.push("a\x1b]9;Bu"));
+        assert_eq!("b", stripper.push("ild\x07b"));
+        drop(stripper);
+        assert_eq!(vec![(9, "Build".to_string())], notes);
+    }
+
+    #[test]
+    fn clipboard_extracted() {
+        let mut copied = vec![];
+        let mut stripper = AnsiStripperBuilder::new()
+            .osc52_extract(true)
+            .stateful()
+            .on_clipboard(|selection, data| copied.push((selection.to_string(), data.to_vec())));
+        assert_eq!("a", stripper.push("a\x1b]52;c;aGVs"));
+        assert_eq!("bc", stripper.push("bG8=\x07b\x1b]52;p;?\x07c"));
+        drop(stripper);
+        assert_eq!(vec![("c".to_string(), b"hello".to_vec())], copied);
+    }
+
+    #[test]
+    fn clipboard_needs_option() {
+        let mut copied = 0;
+        let mut stripper = StatefulStripper::new().on_clipboard(|_, _| copied += 1);
+        assert_eq!("ab", stripper.push("a\x1b]52;c;aGVsbG8=\x07b"));
+        drop(stripper);
+        assert_eq!(0, copied);
+    }
+
+    fn fed(state: &mut StripState, chunk: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        state.feed(chunk, &mut |run| out.extend_from_slice(run));
+        out
+    }
+
+    #[test]
+    fn state_fed_in_chunks() {
+        let mut state = StripState::new();
+        assert_eq!(b"a", &fed(&mut state, b"a\x1b[3")[..]);
+        assert_eq!(b"b", &fed(&mut state, b"1mb")[..]);
+        assert!(fed(&mut state, b"\x1b]0;t\x1b").is_empty());
+        assert_eq!(b"\xffc", &fed(&mut state, b"\\\xff\x1b\x1b[0mc")[..]);
+
+        let mut state = StripState::new();
+        let mut out = Vec::new();
+        state.feed(b"a\x1b[3", &mut |run| out.push(run.to_vec()));
+        state.feed(b"1mb", &mut |run| out.push(run.to_vec()));
+        state.finish(&mut |run| out.push(run.to_vec()));
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], out);
+    }
+
+    #[test]
+    fn state_long_payload_in_small_chunks() {
+        // Each chunk is only looked at once, however long the sequence it's in gets.
+        let mut stream = b"a\x1b]0;".to_vec();
+        stream.resize(400_000, b'x');
+        stream.extend_from_slice(b"\x1b\\b\x1bPq");
+        stream.resize(800_000, b'#');
+        stream.extend_from_slice(b"\x1b\x1b\\c");
+        let mut state = StripState::new();
+        let mut out = Vec::new();
+        for chunk in stream.chunks(7) {
+            state.feed(chunk, &mut |run| out.extend_from_slice(run));
+        }
+        assert_eq!(b"abc", &out[..]);
+    }
+
+    #[test]
+    fn state_finish_drops_incomplete() {
+        let mut state = StripState::new();
+        assert_eq!(b"a", &fed(&mut state, b"a\x1b]0;unterm")[..]);
+        let mut rest = Vec::new();
+        state.finish(&mut |run| rest.extend_from_slice(run));
+        assert!(rest.is_empty());
+        // A new stream starts clean.
+        assert_eq!(b"m", &fed(&mut state, b"m")[..]);
+    }
+
+    #[test]
+    fn builder_options_apply() {
+        let mut stripper = AnsiStripperBuilder::new()
+            .keep_exact(&["\x1b[1m"])
+            .stateful();
+        assert_eq!("a", stripper.push("a\x1b["));
+        assert_eq!("\x1b[1mb", stripper.push("1mb\x1b[0m"));
+    }
+}
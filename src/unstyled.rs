@@ -0,0 +1,207 @@
+//! Bridge for output built with the `anstyle` ecosystem.
+//!
+//! `anstyle::Style::render()` and friends hand back `Display` values, so one half of the
+//! bridge is a `Display` adapter: wrap anything that renders styled text and it renders the
+//! same text with the escapes stripped by this crate's state machine. The other half goes
+//! the other way, reading the SGRs in text as `anstyle::Style`s.
+
+use core::fmt::{self, Write};
+
+use anstyle::{Ansi256Color, AnsiColor, Color, Effects, RgbColor, Style};
+
+use crate::{styled_segments, transition, Action, Mode, Sgr, SgrColor, StyledSegments};
+
+/// Display adapter that renders the wrapped value and strips any ANSI escapes from it.
+///
+/// The output is stripped as the value writes it, so nothing is buffered, and a sequence
+/// written in several pieces is still removed whole.
+pub struct Unstyled<D>(pub D);
+
+impl<D: fmt::Display> fmt::Display for Unstyled<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            StripFmt {
+                inner: f,
+                mode: Mode::Normal,
+            },
+            "{}",
+            self.0
+        )
+    }
+}
+
+/// A `fmt::Write` that passes on only the chars the state machine keeps, carrying its mode
+/// from one `write_str` to the next.
+struct StripFmt<W> {
+    inner: W,
+    mode: Mode,
+}
+
+impl<W: Write> Write for StripFmt<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut run = 0..0;
+        for (i, c) in s.char_indices() {
+            let action;
+            (self.mode, action) = transition(self.mode, c);
+            if action == Action::Keep {
+                if run.end != i {
+                    self.inner.write_str(&s[run])?;
+                    run = i..i;
+                }
+                run.end = i + c.len_utf8();
+            }
+        }
+        self.inner.write_str(&s[run])
+    }
+}
+
+impl From<SgrColor> for Color {
+    fn from(colour: SgrColor) -> Self {
+        use SgrColor::*;
+        let ansi = match colour {
+            Black => AnsiColor::Black,
+            Red => AnsiColor::Red,
+            Green => AnsiColor::Green,
+            Yellow => AnsiColor::Yellow,
+            Blue => AnsiColor::Blue,
+            Magenta => AnsiColor::Magenta,
+            Cyan => AnsiColor::Cyan,
+            White => AnsiColor::White,
+            BrightBlack => AnsiColor::BrightBlack,
+            BrightRed => AnsiColor::BrightRed,
+            BrightGreen => AnsiColor::BrightGreen,
+            BrightYellow => AnsiColor::BrightYellow,
+            BrightBlue => AnsiColor::BrightBlue,
+            BrightMagenta => AnsiColor::BrightMagenta,
+            BrightCyan => AnsiColor::BrightCyan,
+            BrightWhite => AnsiColor::BrightWhite,
+            Indexed(n) => return Color::Ansi256(Ansi256Color(n)),
+            Rgb(r, g, b) => return Color::Rgb(RgbColor(r, g, b)),
+        };
+        Color::Ansi(ansi)
+    }
+}
+
+impl From<Sgr> for Style {
+    fn from(sgr: Sgr) -> Self {
+        let mut effects = Effects::new();
+        for (on, effect) in [
+            (sgr.bold, Effects::BOLD),
+            (sgr.dim, Effects::DIMMED),
+            (sgr.italic, Effects::ITALIC),
+            (sgr.underline, Effects::UNDERLINE),
+            (sgr.blink, Effects::BLINK),
+            (sgr.reverse, Effects::INVERT),
+            (sgr.conceal, Effects::HIDDEN),
+            (sgr.strikethrough, Effects::STRIKETHROUGH),
+        ] {
+            effects = effects.set(effect, on);
+        }
+        Style::new()
+            .fg_color(sgr.foreground.map(Color::from))
+            .bg_color(sgr.background.map(Color::from))
+            .effects(effects)
+    }
+}
+
+/// Split `s` into its runs of visible text, each with the `anstyle::Style` in effect for it.
+///
+/// `styled_segments` with the ecosystem's type, so a run can be re-rendered with
+/// `style.render()` or handed to anything else that takes a `Style`.
+pub fn anstyle_segments(s: &str) -> AnstyleSegments<'_> {
+    AnstyleSegments(styled_segments(s))
+}
+
+/// At each iteration, returns the next run of visible text and its `anstyle::Style`.
+pub struct AnstyleSegments<'a>(StyledSegments<'a>);
+
+impl<'a> Iterator for AnstyleSegments<'a> {
+    type Item = (&'a str, Style);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(text, sgr)| (text, sgr.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    /// Writes its text a char at a time, splitting every escape across writes.
+    struct Trickle<'a>(&'a str);
+
+    impl fmt::Display for Trickle<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.chars().try_for_each(|c| f.write_char(c))
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let style = Style::new()
+            .fg_color(Some(Color::Ansi(AnsiColor::Red)))
+            .bg_color(Some(Color::Rgb(RgbColor(1, 2, 3))))
+            .bold();
+        let colored = format!("{}hello{} world", style.render(), style.render_reset());
+        assert_ne!("hello world", colored);
+        assert_eq!("hello world", Unstyled(&colored).to_string());
+        assert_eq!(
+            vec![("hello", style), (" world", Style::new())],
+            anstyle_segments(&colored).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn split_writes() {
+        let sample = "a\x1b[1;31mb\x1b]0;t\x1b\\c\x1b[3\nd\u{9b}0mé";
+        assert_eq!(
+            crate::strip_ansi(sample),
+            Unstyled(Trickle(sample)).to_string()
+        );
+        assert_eq!(
+            "ab",
+            format!("{}", Unstyled(format_args!("a{}b", "\x1b[0m")))
+        );
+    }
+
+    #[test]
+    fn plain_passes_through() {
+        assert_eq!("plain", Unstyled("plain").to_string());
+        assert_eq!(
+            vec![("plain", Style::new())],
+            anstyle_segments("plain").collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn effects() {
+        assert_eq!(
+            vec![
+                ("x", Style::new().dimmed().invert().strikethrough()),
+                ("y", Style::new().blink().hidden()),
+            ],
+            anstyle_segments("\x1b[2;7;9mx\x1b[0;5;8my").collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn colour_forms() {
+        let styles: Vec<Style> = anstyle_segments("\x1b[3;4;38;5;208ma\x1b[23;96;48;2;1;2;3mb")
+            .map(|(_, style)| style)
+            .collect();
+        assert_eq!(
+            vec![
+                Style::new()
+                    .italic()
+                    .underline()
+                    .fg_color(Some(Color::Ansi256(Ansi256Color(208)))),
+                Style::new()
+                    .underline()
+                    .fg_color(Some(Color::Ansi(AnsiColor::BrightCyan)))
+                    .bg_color(Some(Color::Rgb(RgbColor(1, 2, 3)))),
+            ],
+            styles
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Display-width aware helpers, using the `unicode-width` model of columns.
+
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use core::iter;
+
+use unicode_width::UnicodeWidthChar;
+
+#[cfg(feature = "alloc")]
+use crate::strip_ansi;
+use crate::AnsiStripper;
+
+/// The columns `c` takes up: 2 for wide and fullwidth chars, 0 for combining marks.
+///
+/// Control characters that survive stripping, such as tabs, count as a single column.
+pub(crate) fn char_width(c: char) -> usize {
+    c.width().unwrap_or(1)
+}
+
+impl AnsiStripper<'_> {
+    /// The display width of the remaining visible text, as `char_width` counts columns.
+    pub fn visible_width(self) -> usize {
+        self.map(|run| run.chars().map(char_width).sum::<usize>())
+            .sum()
+    }
+}
+
+/// The byte length of the first chunk of `rest` that fits in `width` columns.
+#[cfg(feature = "alloc")]
+fn chunk_end(rest: &str, width: usize) -> usize {
+    let mut used = 0;
+    for (i, c) in rest.char_indices() {
+        let w = char_width(c);
+        if w > 0 && used + w > width && i > 0 {
+            return i;
+        }
+        used += w;
+    }
+    rest.len()
+}
+
+/// Strip `s` and yield the visible text in chunks of at most `width` columns.
+///
+/// A wide char is never split across chunks, zero-width chars stay with the char before
+/// them, and a char wider than `width` gets a chunk to itself.
+///
+/// # Panics
+///
+/// If `width` is 0.
+#[cfg(feature = "alloc")]
+pub fn strip_chunks(s: &str, width: usize) -> impl Iterator<Item = String> {
+    assert!(width > 0, "chunk width must be non-zero");
+    let plain = strip_ansi(s);
+    let mut start = 0;
+    iter::from_fn(move || {
+        let rest = &plain[start..];
+        if rest.is_empty() {
+            return None;
+        }
+        let end = chunk_end(rest, width);
+        start += end;
+        Some(rest[..end].to_string())
+    })
+}
+
+/// The number of lines `s` takes up once stripped and wrapped at `width` columns.
+///
+/// Each hard newline starts a line, counted as `str::lines` counts them, and each line wraps
+/// as `strip_chunks` splits it. An empty line still takes up one line.
+///
+/// # Panics
+///
+/// If `width` is 0.
+#[cfg(feature = "alloc")]
+pub fn wrapped_line_count(s: &str, width: usize) -> usize {
+    assert!(width > 0, "wrap width must be non-zero");
+    let plain = strip_ansi(s);
+    plain
+        .lines()
+        .map(|mut line| {
+            let mut count = 1;
+            loop {
+                line = &line[chunk_end(line, width)..];
+                if line.is_empty() {
+                    break count;
+                }
+                count += 1;
+            }
+        })
+        .sum()
+}
+
+/// Strip `s` and fit each line to exactly `width` columns, padding with spaces or truncating.
+///
+/// Lines are as `str::lines` has them, and a trailing newline is kept. A wide char that
+/// would straddle the edge is dropped and the gap padded, so no line ends up too wide.
+#[cfg(feature = "alloc")]
+pub fn strip_pad_lines(s: &str, width: usize) -> String {
+    let plain = strip_ansi(s);
+    let mut out = String::with_capacity(plain.len());
+    for (i, line) in plain.lines().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut used = 0;
+        for c in line.chars() {
+            let w = char_width(c);
+            if used + w > width {
+                break;
+            }
+            used += w;
+            out.push(c);
+        }
+        out.extend(iter::repeat_n(' ', width - used));
+    }
+    if plain.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "alloc")]
+    use super::*;
+    use crate::NonEsc;
+
+    #[cfg(feature = "alloc")]
+    fn chunks(s: &str, width: usize) -> Vec<String> {
+        strip_chunks(s, width).collect()
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn ascii_chunks() {
+        assert_eq!(
+            vec!["hello", " worl", "d"],
+            chunks("\x1b[31mhello world\x1b[0m", 5)
+        );
+        assert_eq!(vec!["ab"], chunks("a\x1b[0mb", 5));
+        assert!(chunks("\x1b[0m", 5).is_empty());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn wide_chars_not_split() {
+        assert_eq!(vec!["漢", "字a", "bc"], chunks("漢\x1b[1m字abc", 3));
+        assert_eq!(vec!["漢", "字"], chunks("漢字", 1));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn combining_marks_stay_attached() {
+        assert_eq!(vec!["e\u{301}", "e\u{301}"], chunks("e\u{301}e\u{301}", 1));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn wrapped_lines() {
+        let sample = "\x1b[1;31merror\x1b[0m: something broke\n\n\x1b[2mhint\x1b[0m\n";
+        assert_eq!(5, wrapped_line_count(sample, 8));
+        assert_eq!(3, wrapped_line_count(sample, 80));
+        assert_eq!(0, wrapped_line_count("\x1b[0m", 8));
+        assert_eq!(1, wrapped_line_count("abc\x1b[0m\r\n", 3));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn wrapped_lines_wide_chars() {
+        // 字 won't fit in the column left after 漢 and a.
+        assert_eq!(2, wrapped_line_count("漢a\x1b[31m字", 4));
+        assert_eq!(4, wrapped_line_count("漢字\n\x1b[0m漢字", 3));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    #[should_panic(expected = "non-zero")]
+    fn zero_wrap_width_panics() {
+        wrapped_line_count("abc", 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    #[should_panic(expected = "non-zero")]
+    fn zero_width_panics() {
+        let _ = strip_chunks("abc", 0);
+    }
+
+    #[test]
+    fn visible_width() {
+        assert_eq!(3, "a\x1b[31m漢\x1b[0m".non_esc().visible_width());
+        assert_eq!(4, "\x1b[1me\u{301}\t😀\x1b[0m".non_esc().visible_width());
+        assert_eq!(0, "\x1b[0m".non_esc().visible_width());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn pad_lines() {
+        let colored = "\x1b[1mname\x1b[0m\n\x1b[31m漢字\x1b[0m漢\nsomething much longer\n\nab漢\n";
+        let padded = strip_pad_lines(colored, 5);
+        assert_eq!("name \n漢字 \nsomet\n     \nab漢 \n", padded);
+        for line in padded.lines() {
+            assert_eq!(5, line.chars().map(char_width).sum::<usize>(), "{line:?}");
+        }
+        assert_eq!("ab   ", strip_pad_lines("a\x1b[0mb", 5));
+        assert_eq!("", strip_pad_lines("", 5));
+    }
+}
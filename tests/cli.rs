@@ -0,0 +1,305 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run the binary with `args`, feeding `input` to stdin.
+fn run(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ansi-strip"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start ansi-strip");
+    // Given files, it never reads stdin and may be gone before this is written.
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+fn colored() -> Vec<u8> {
+    fs::read("tests/data/colored.txt").unwrap()
+}
+
+#[test]
+fn strips_stdin() {
+    let output = run(&[], &colored());
+    assert!(output.status.success());
+    assert_eq!(
+        "plain line\nerror: broken\ncleared\nlast\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn stats_only() {
+    let output = run(&["--stats-only"], &colored());
+    assert!(output.status.success());
+    assert_eq!(
+        "escapes: 4\nbytes removed: 25\nlines affected: 2\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn unknown_argument() {
+    let output = run(&["--bogus"], b"");
+    assert_eq!(Some(2), output.status.code());
+}
+
+/// A sixel image (a DCS) whose data runs over several lines.
+const SIXEL: &[u8] =
+    b"before\x1bPq#0;2;0;0;0#1;2;100;100;0\n#1~~@@vv@@~~@@~~$\n#2??}}GG}}??}}??-\n\x1b\\after\n";
+
+#[test]
+fn whole_strips_multiline_sequences() {
+    let output = run(&["--whole"], SIXEL);
+    assert!(output.status.success());
+    assert_eq!("beforeafter\n", String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn lines_confine_sequences() {
+    let output = run(&[], SIXEL);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_ne!("beforeafter\n", stdout);
+    assert!(stdout.starts_with("before\n"));
+    assert!(stdout.contains("#1~~@@vv@@~~@@~~$"));
+}
+
+#[test]
+fn whole_stats_only() {
+    let output = run(&["--whole", "--stats-only"], &colored());
+    assert!(output.status.success());
+    assert_eq!(
+        "escapes: 4\nbytes removed: 25\nlines affected: 2\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let output = run(&["--whole", "--stats-only"], SIXEL);
+    assert_eq!(
+        "escapes: 1\nbytes removed: 66\nlines affected: 4\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn help() {
+    let output = run(&["--help"], b"");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("--stats-only"));
+    assert!(stdout.contains("--whole"));
+    assert!(stdout.contains("--version"));
+}
+
+#[test]
+fn version() {
+    let output = run(&["--version"], b"");
+    assert!(output.status.success());
+    assert_eq!(
+        format!("ansi-strip {}\n", env!("CARGO_PKG_VERSION")),
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn repeated_flag_rejected() {
+    let output = run(&["--whole", "--whole"], b"");
+    assert_eq!(Some(2), output.status.code());
+}
+
+fn stdout_of(args: &[&str], input: &[u8]) -> String {
+    let output = run(args, input);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn trailing_newline_auto() {
+    assert_eq!("a\nb\n", stdout_of(&[], b"a\n\x1b[1mb\n"));
+    assert_eq!("a\nb", stdout_of(&[], b"a\n\x1b[1mb"));
+    assert_eq!("a\nb", stdout_of(&["--trailing-newline=auto"], b"a\nb"));
+    assert_eq!("a\nb", stdout_of(&["--whole"], b"a\n\x1b[1mb"));
+    assert_eq!("", stdout_of(&[], b""));
+}
+
+#[test]
+fn trailing_newline_always() {
+    for input in [&b"a\n\x1b[1mb\n"[..], b"a\n\x1b[1mb"] {
+        assert_eq!("a\nb\n", stdout_of(&["--trailing-newline=always"], input));
+        assert_eq!(
+            "a\nb\n",
+            stdout_of(&["--whole", "--trailing-newline=always"], input)
+        );
+    }
+    assert_eq!("", stdout_of(&["--trailing-newline=always"], b""));
+}
+
+#[test]
+fn trailing_newline_never() {
+    for input in [&b"a\n\x1b[1mb\n"[..], b"a\n\x1b[1mb"] {
+        assert_eq!("a\nb", stdout_of(&["--trailing-newline=never"], input));
+        assert_eq!(
+            "a\nb",
+            stdout_of(&["--whole", "--trailing-newline=never"], input)
+        );
+    }
+    assert_eq!("a\n", stdout_of(&["--trailing-newline=never"], b"a\n\n"));
+}
+
+#[test]
+fn trailing_newline_invalid() {
+    let output = run(&["--trailing-newline=sometimes"], b"");
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn log_escapes() {
+    let output = run(
+        &["--log-escapes"],
+        b"a\x1b[31mred\x1b[0m\n\x1b]0;t\\x\x07b\n\x1b[",
+    );
+    assert!(output.status.success());
+    assert_eq!("ared\nb\n", String::from_utf8(output.stdout).unwrap());
+    assert_eq!(
+        "1: \\x1b[31m\n9: \\x1b[0m\n14: \\x1b]0;t\\x5cx\\x07\n24: \\x1b[\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn keep_color() {
+    let input = b"\x1b[2J\x1b[1;31merror\x1b[0m: \x1b]0;title\x07\x1b[Kbroken\n";
+    assert_eq!(
+        "\x1b[1;31merror\x1b[0m: broken\n",
+        stdout_of(&["--keep-color"], input)
+    );
+    assert_eq!(
+        "\x1b[1;31merror\x1b[0m: broken\n",
+        stdout_of(&["-c"], input)
+    );
+    assert_eq!(
+        "\u{9b}32mok\u{9b}0m\n",
+        stdout_of(&["-c"], "\u{9b}32mok\u{9b}0m\u{9b}2J\n".as_bytes())
+    );
+    assert_eq!("error: broken\n", stdout_of(&[], input));
+
+    let output = run(&["-c", "--log-escapes"], input);
+    assert_eq!(
+        "0: \\x1b[2J\n22: \\x1b]0;title\\x07\n32: \\x1b[K\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+/// Write `contents` to a file in the temp dir named for this test, returning its path.
+fn temp_file(name: &str, contents: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("ansi-strip-{}-{name}", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn exact_newlines() {
+    let input = b"\x1b[1mone\x1b[0m\r\n\r\ntwo\x1b[K\n\nthree\x1b[0m";
+    let expected = b"one\r\n\r\ntwo\n\nthree";
+    for args in [&[][..], &["--whole"]] {
+        let output = run(args, input);
+        assert!(output.status.success());
+        assert_eq!(&expected[..], &output.stdout[..], "{args:?}");
+    }
+}
+
+#[test]
+fn file_arguments() {
+    let a = temp_file("a.log", b"\x1b[31ma1\x1b[0m\na2\x1b[K");
+    let b = temp_file("b.log", b"\x1b]0;t\x07b1\r\n");
+    assert_eq!("a1\na2b1\r\n", stdout_of(&[&a, &b], b"ignored\n"));
+    assert_eq!("a1\na2b1\r\n", stdout_of(&["--whole", &a, &b], b""));
+    assert_eq!("b1\r\na1\na2", stdout_of(&[&b, &a], b""));
+}
+
+#[test]
+fn missing_file() {
+    let a = temp_file("present.log", b"\x1b[1ma\x1b[0m\n");
+    let output = run(&["no-such-file.log", &a], b"");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("a\n", String::from_utf8(output.stdout).unwrap());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("ansi-strip: no-such-file.log: "),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn invalid_utf8() {
+    let input = b"\xff\x1b[1mok\n\x1b]0;\xfe\x07a\xc3\n\xe2\x9c\x93\x1b[0m\n";
+    let expected = b"\xffok\na\xc3\n\xe2\x9c\x93\n";
+    for args in [&[][..], &["--whole"]] {
+        let output = run(args, input);
+        assert!(output.status.success(), "{args:?}");
+        assert_eq!(&expected[..], &output.stdout[..], "{args:?}");
+    }
+
+    let output = run(&["--log-escapes"], input);
+    assert_eq!(
+        "1: \\x1b[1m\n8: \\x1b]0;\\xfe\\x07\n20: \\x1b[0m\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+    assert_eq!(
+        "escapes: 3\nbytes removed: 14\nlines affected: 3\n",
+        stdout_of(&["--stats-only"], input)
+    );
+}
+
+#[test]
+fn stdin_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ansi-strip"))
+        .stdin(fs::File::open("tests").unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("ansi-strip: stdin: "), "{stderr}");
+}
+
+#[test]
+fn stdout_closed() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ansi-strip"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start ansi-strip");
+    // Like `| head -1`: the reader goes away before most of the output is written.
+    drop(child.stdout.take());
+    let input = "\x1b[1mline\x1b[0m\n".repeat(200_000);
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output.status);
+    assert_eq!("", String::from_utf8(output.stderr).unwrap());
+}
+
+#[test]
+fn list_supported() {
+    let plain = stdout_of(&["--list-supported"], b"\x1b[1mignored\n");
+    assert!(
+        plain.lines().all(|line| line.ends_with("  stripped")),
+        "{plain}"
+    );
+    assert!(plain.contains("OSC, ended by BEL"));
+    assert!(plain.contains("C1 introducers"));
+
+    let colored = stdout_of(&["--list-supported", "--keep-color"], b"");
+    assert_ne!(plain, colored);
+    let sgr = colored.lines().find(|line| line.contains("SGR")).unwrap();
+    assert!(sgr.ends_with("  kept"), "{sgr}");
+    assert_eq!(
+        1,
+        colored
+            .lines()
+            .filter(|line| line.ends_with("kept"))
+            .count()
+    );
+}
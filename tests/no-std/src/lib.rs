@@ -0,0 +1,35 @@
+//! Uses the iterator API from a `no_std` crate, to check it builds without `std` or `alloc`.
+
+#![no_std]
+
+use ansi_strip::{AnsiStripperBuilder, EscOnly, NonEsc, NonEscBytes};
+
+/// Copy the visible bytes of `src` into `out`, returning how many fit.
+pub fn strip_into(src: &str, out: &mut [u8]) -> usize {
+    let mut len = 0;
+    for span in src.non_esc() {
+        let n = span.len().min(out.len() - len);
+        out[len..len + n].copy_from_slice(&span.as_bytes()[..n]);
+        len += n;
+    }
+    len
+}
+
+/// The number of escape sequences in `src`.
+pub fn escapes(src: &str) -> usize {
+    src.esc_only().count()
+}
+
+/// The number of visible bytes in `src`, which needn't be UTF-8.
+pub fn visible_bytes(src: &[u8]) -> usize {
+    src.non_esc_bytes().map(<[u8]>::len).sum()
+}
+
+/// The number of bytes left in `src` by a stripper that keeps SGRs.
+pub fn styled_bytes(src: &str) -> usize {
+    AnsiStripperBuilder::new()
+        .keep_sgr(true)
+        .build(src)
+        .map(str::len)
+        .sum()
+}
@@ -0,0 +1,226 @@
+use ansi_strip::{
+    count_escapes, escape_count, strip_ansi, strip_in_place, strip_len, transition, Action,
+    AnsiStripperBuilder, Mode, NonEsc, NonEscBytes, Segment, Segmented, TextSpans,
+};
+use proptest::prelude::*;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Strings dense in escape introducers, terminators and parameter bytes.
+fn escapey() -> impl Strategy<Value = String> {
+    proptest::string::string_regex("[\x1b\x1b\x1b\\[\\]P_X^\\\\\x07;0-9m~a-c \t\n😀漢]{0,64}")
+        .unwrap()
+}
+
+/// Long plain runs, with the odd sequence, C1 control or char that merely looks like one.
+fn mostly_plain() -> impl Strategy<Value = String> {
+    proptest::string::string_regex(
+        "([a-z é°£\u{85}\t\n]{0,40}(\x1b\\[[0-9;]{0,4}m|\u{9b}1m|\u{9d}0;t\u{9c}|\x1b)?){0,8}",
+    )
+    .unwrap()
+}
+
+/// Escape-dense ASCII, which the stripper runs over without decoding chars.
+fn ascii() -> impl Strategy<Value = String> {
+    proptest::string::string_regex("[\x1b\x1b\x1b\\[\\]P_X^\\\\\x07;0-9m~a-c \t\n]{0,64}").unwrap()
+}
+
+/// What `non_esc` keeps, worked out a char at a time from the state machine alone: just the
+/// chars it calls `Keep`.
+fn kept_by_transition(s: &str) -> String {
+    let mut mode = Mode::Normal;
+    s.chars()
+        .filter(|&c| {
+            let action;
+            (mode, action) = transition(mode, c);
+            action == Action::Keep
+        })
+        .collect()
+}
+
+/// Run `f` on its own thread, panicking if it hasn't finished within a few seconds.
+///
+/// A stripper stuck in a loop that never yields would otherwise hang the test run.
+fn with_timeout<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || tx.send(f()));
+    rx.recv_timeout(Duration::from_secs(5))
+        .expect("stripper didn't finish")
+}
+
+fn is_subsequence(needle: &[u8], haystack: &[u8]) -> bool {
+    let mut haystack = haystack.iter();
+    needle.iter().all(|b| haystack.any(|h| h == b))
+}
+
+proptest! {
+    #[test]
+    fn output_is_subsequence_of_input(s in escapey()) {
+        let stripped: String = s.as_str().non_esc().collect();
+        prop_assert!(is_subsequence(stripped.as_bytes(), s.as_bytes()));
+    }
+
+    #[test]
+    fn output_has_no_esc(s in escapey()) {
+        prop_assert!(s.as_str().non_esc().all(|span| !span.contains('\x1b')));
+    }
+
+    #[test]
+    fn spans_are_in_order_and_disjoint(s in escapey()) {
+        let base = s.as_ptr() as usize;
+        let mut last_end = 0;
+        for span in s.as_str().non_esc() {
+            let start = span.as_ptr() as usize - base;
+            prop_assert!(start >= last_end);
+            last_end = start + span.len();
+        }
+        prop_assert!(last_end <= s.len());
+    }
+
+    #[test]
+    fn consecutive_spans_never_touch(s in escapey()) {
+        // If they did, they could have been yielded as one span.
+        let base = s.as_ptr() as usize;
+        let mut last_end = None;
+        for span in s.as_str().non_esc() {
+            let start = span.as_ptr() as usize - base;
+            prop_assert_ne!(Some(start), last_end);
+            last_end = Some(start + span.len());
+        }
+    }
+
+    #[test]
+    fn segments_round_trip(s in escapey()) {
+        let all: String = s.as_str().segments().map(|seg| seg.as_str()).collect();
+        prop_assert_eq!(&s, &all);
+        let text: String = s
+            .as_str()
+            .segments()
+            .filter_map(|seg| match seg {
+                Segment::Text(t) => Some(t),
+                Segment::Escape(_) => None,
+            })
+            .collect();
+        prop_assert_eq!(s.as_str().non_esc().collect::<String>(), text);
+    }
+
+    #[test]
+    fn matches_char_by_char(s in prop_oneof![escapey(), mostly_plain()]) {
+        let expected = kept_by_transition(&s);
+        prop_assert_eq!(&expected, &s.as_str().non_esc().collect::<String>());
+        let bytes: Vec<u8> = AnsiStripperBuilder::new()
+            .from_bytes(s.as_bytes())
+            .flatten()
+            .copied()
+            .collect();
+        // Bytes don't see C1 controls, so compare those only when there aren't any.
+        if !s.chars().any(|c| ('\u{80}'..='\u{9f}').contains(&c)) {
+            prop_assert_eq!(expected.as_bytes(), &bytes[..]);
+        }
+    }
+
+    #[test]
+    fn size_hint_is_an_upper_bound(s in prop_oneof![escapey(), mostly_plain()]) {
+        let builders = [
+            AnsiStripperBuilder::new(),
+            AnsiStripperBuilder::new().pad_removed(true).keep_sgr(true),
+            AnsiStripperBuilder::new().line_color_simplify(true),
+            AnsiStripperBuilder::new().osc8_as_markdown(true).lenient(true),
+        ];
+        for builder in builders {
+            let mut stripper = builder.build(&s);
+            let mut uppers = vec![stripper.size_hint().1.unwrap()];
+            while stripper.next().is_some() {
+                uppers.push(stripper.size_hint().1.unwrap());
+            }
+            let items = uppers.len() - 1;
+            for (taken, upper) in uppers.into_iter().enumerate() {
+                prop_assert!(upper >= items - taken, "{} left after {}", upper, taken);
+            }
+        }
+    }
+
+    #[test]
+    fn strip_len_matches_strip(s in prop_oneof![escapey(), mostly_plain()]) {
+        // Every prefix, so plenty of them end partway through a sequence.
+        for (end, _) in s.char_indices().chain([(s.len(), ' ')]) {
+            let prefix = &s[..end];
+            prop_assert_eq!(strip_ansi(prefix).len(), strip_len(prefix), "{:?}", prefix);
+        }
+    }
+
+    #[test]
+    fn stats_add_up(s in prop_oneof![escapey(), mostly_plain()]) {
+        let stats = count_escapes(&s);
+        prop_assert_eq!(s.len(), stats.text_bytes + stats.escape_bytes);
+        prop_assert_eq!(strip_len(&s), stats.text_bytes);
+        prop_assert_eq!(escape_count(&s), stats.escape_count);
+    }
+
+    #[test]
+    fn in_place_matches_strip(s in prop_oneof![escapey(), mostly_plain()]) {
+        let mut buf = s.clone();
+        strip_in_place(&mut buf);
+        prop_assert_eq!(strip_ansi(&s), buf);
+    }
+
+    #[test]
+    fn spans_map_back_to_input(s in prop_oneof![escapey(), mostly_plain()]) {
+        let mut last_end = 0;
+        let mut joined = String::new();
+        for (range, run) in s.as_str().spans() {
+            prop_assert!(range.start >= last_end && range.start < range.end);
+            prop_assert_eq!(&s[range.clone()], run);
+            last_end = range.end;
+            joined.push_str(run);
+        }
+        prop_assert_eq!(s.as_str().non_esc().collect::<String>(), joined);
+    }
+
+    #[test]
+    fn ascii_path_matches_general(s in ascii()) {
+        // A leading non-ASCII char sends the same input down the path that decodes chars.
+        let decoded = format!("é{s}");
+        let general: Vec<_> = decoded
+            .as_str()
+            .spans()
+            .map(|(range, _)| range.start.saturating_sub(2)..range.end - 2)
+            .filter(|range| !range.is_empty())
+            .collect();
+        let fast: Vec<_> = s.as_str().spans().map(|(range, _)| range).collect();
+        prop_assert_eq!(general, fast);
+        prop_assert_eq!(kept_by_transition(&s), strip_ansi(&s));
+    }
+
+    #[test]
+    fn str_and_bytes_identical(s in escapey()) {
+        let from_str: Vec<&[u8]> = s.as_str().non_esc().map(str::as_bytes).collect();
+        let from_bytes: Vec<&[u8]> = AnsiStripperBuilder::new().from_bytes(s.as_bytes()).collect();
+        prop_assert_eq!(from_str, from_bytes);
+    }
+
+    #[test]
+    fn always_terminates(
+        s in prop_oneof![escapey(), mostly_plain(), any::<String>()],
+        bytes in any::<Vec<u8>>(),
+    ) {
+        // Each item takes at least one byte, bar a final reset or link close.
+        let counts = with_timeout(move || {
+            let builders = [
+                AnsiStripperBuilder::new(),
+                AnsiStripperBuilder::new().pad_removed(true).linux_console(true),
+                AnsiStripperBuilder::new().line_color_simplify(true),
+                AnsiStripperBuilder::new().osc8_as_markdown(true).lenient(true),
+            ];
+            let mut counts = vec![(bytes.len(), bytes.as_slice().non_esc_bytes().count())];
+            for builder in builders {
+                counts.push((s.len() + 3, builder.build(&s).take(s.len() + 4).count()));
+            }
+            counts
+        });
+        for (cap, count) in counts {
+            prop_assert!(count <= cap, "{} items from {} bytes", count, cap);
+        }
+    }
+}